@@ -0,0 +1,120 @@
+use anyhow::Result;
+use glog_rust::io::{
+    codec::{
+        Compressor, Decompressor, Lz4Compressor, Lz4Decompressor, ZlibCompressor, ZlibDecompressor,
+        ZstdCompressor, ZstdDecompressor,
+    },
+    primitive::SINGLE_LOG_CONTENT_MAX_LENGTH,
+};
+use std::time::{Duration, Instant};
+
+const LEVELS: [&str; 5] = ["1", "2", "3", "4", "5"];
+const MESSAGES: [&str; 6] = [
+    "save order draft",
+    "request /api/v2/order/list finished in 132ms",
+    "websocket reconnect, retry count",
+    "location updated lat=22.543096 lng=114.057865",
+    "page OrderDetailActivity onResume",
+    "upload image failed: timeout",
+];
+
+fn create_logs(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| {
+            format!(
+                r#"{{"msg":"{}:{}","level":"{}","timestamp":"2023-08-03 08:{:02}:{:02} +0000","userId":"uid{}","namespace":"namespace{}"}}"#,
+                MESSAGES[i % MESSAGES.len()],
+                i,
+                LEVELS[i % LEVELS.len()],
+                (i / 60) % 60,
+                i % 60,
+                10000 + i % 13,
+                i % 3
+            )
+        })
+        .collect()
+}
+
+struct Report {
+    compressed: usize,
+    compress_time: Duration,
+    decompress_time: Duration,
+}
+
+fn bench(
+    logs: &[String],
+    compressor: &mut dyn Compressor,
+    decompressor: &mut dyn Decompressor,
+) -> Result<Report> {
+    let start = Instant::now();
+    let mut records = Vec::with_capacity(logs.len());
+    for log in logs {
+        records.push(compressor.compress(log.as_bytes())?);
+    }
+    let compress_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut output = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+    for (record, log) in records.iter().zip(logs) {
+        output.clear();
+        decompressor.decompress(record, &mut output)?;
+        assert_eq!(output, log.as_bytes());
+    }
+    let decompress_time = start.elapsed();
+
+    Ok(Report {
+        compressed: records.iter().map(Vec::len).sum(),
+        compress_time,
+        decompress_time,
+    })
+}
+
+fn print_report(name: &str, original: usize, report: &Report) {
+    let mb = original as f64 / 1024.0 / 1024.0;
+    println!(
+        "{:<16} ratio {:>5.2}  compress {:>8.1} MB/s  decompress {:>8.1} MB/s",
+        name,
+        original as f64 / report.compressed as f64,
+        mb / report.compress_time.as_secs_f64(),
+        mb / report.decompress_time.as_secs_f64(),
+    );
+}
+
+fn main() -> Result<()> {
+    let logs = create_logs(100_000);
+    let original: usize = logs.iter().map(String::len).sum();
+    let dictionary = zstd::dict::from_samples(&logs[..1000], 16 * 1024)?;
+    let level = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+    println!("{} records, {} bytes", logs.len(), original);
+
+    let report = bench(
+        &logs,
+        &mut ZlibCompressor::default(),
+        &mut ZlibDecompressor::default(),
+    )?;
+    print_report("zlib", original, &report);
+
+    let report = bench(
+        &logs,
+        &mut ZstdCompressor::new(level, &[])?,
+        &mut ZstdDecompressor::new(&[])?,
+    )?;
+    print_report("zstd", original, &report);
+
+    let report = bench(
+        &logs,
+        &mut ZstdCompressor::new(level, &dictionary)?,
+        &mut ZstdDecompressor::new(&dictionary)?,
+    )?;
+    print_report("zstd+dictionary", original, &report);
+
+    let report = bench(
+        &logs,
+        &mut Lz4Compressor::default(),
+        &mut Lz4Decompressor::default(),
+    )?;
+    print_report("lz4", original, &report);
+
+    Ok(())
+}
@@ -1,9 +1,10 @@
 use anyhow::Result;
-use elliptic_curve::pkcs8::der::Writer;
-use glog_rust::cipher::key_pair::KeyPair;
-use std::fs::File;
+use glog_rust::cipher::{key_file::write_private_file, key_pair::KeyPair};
+use std::path::Path;
 
 fn main() -> Result<()> {
+    let force = std::env::args().any(|arg| arg == "--force");
+
     let client_key = KeyPair::random()?;
     let server_key = KeyPair::random()?;
 
@@ -20,14 +21,14 @@ fn main() -> Result<()> {
 
     assert_eq!(shared1, shared2);
 
-    let mut dot_env_file = File::create(".env.local")?;
     let envs = format!(
         r#"PUB_KEY="{}"
 PRI_KEY="{}""#,
         server_key.public_key, server_key.private_key
     );
-    dot_env_file.write(envs.as_bytes())?;
+    write_private_file(Path::new(".env.local"), &envs, force)?;
     println!("server key has been written to .env.local");
+    println!("server key fingerprint: {}", server_key.fingerprint()?);
 
     Ok(())
 }
@@ -1,10 +1,10 @@
 use anyhow::Result;
 use glog_rust::{
+    cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
     io::{
         log_writer::LogBufWriterV4,
         primitive::{CompressMode, EncryptMode},
     },
-    cipher::{key_pair::KeyPair, aes_cfb_ecdh::Cipher},
 };
 
 fn create_logs() -> Vec<String> {
@@ -33,7 +33,7 @@ fn main() -> Result<()> {
     log_buf_writer.write_head()?;
 
     for log in logs {
-        log_buf_writer.write_single_log(&(CompressMode::Zlib, EncryptMode::Aes), &pub_key, &log)?;
+        log_buf_writer.write_single_log((CompressMode::Zlib, EncryptMode::Aes), &pub_key, &log)?;
     }
 
     Ok(())
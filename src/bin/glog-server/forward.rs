@@ -0,0 +1,268 @@
+use glog_rust::{export::RecordSink, format::RecordMeta};
+use std::{
+    sync::{
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+type Sink = Box<dyn RecordSink + Send>;
+
+/// Records a sink may have waiting before further ones are dropped.
+pub const QUEUE_CAPACITY: usize = 10_000;
+
+/// How long a sink waits for more records before it sends what it buffers.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A record on its way to a sink.
+struct Record {
+    file: Arc<str>,
+    index: usize,
+    offset: i64,
+    content: String,
+}
+
+struct Queue {
+    name: String,
+    sender: Option<SyncSender<Record>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Passes the records of every upload on to log backends. Each backend has
+/// its own queue and thread, so a slow or failing backend neither delays
+/// uploads nor fails them; its errors are logged, and records that do not
+/// fit in its queue are dropped.
+pub struct Forwarder {
+    queues: Vec<Queue>,
+    capacity: usize,
+}
+
+impl Default for Forwarder {
+    fn default() -> Self {
+        Self {
+            queues: Vec::new(),
+            capacity: QUEUE_CAPACITY,
+        }
+    }
+}
+
+impl Forwarder {
+    /// Capacity of the queues of the sinks added afterwards.
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, sink: Sink) {
+        let name = name.into();
+        let (sender, receiver) = sync_channel(self.capacity);
+        let thread_name = name.clone();
+        let thread = thread::spawn(move || send_records(&thread_name, sink, receiver));
+        self.queues.push(Queue {
+            name,
+            sender: Some(sender),
+            thread: Some(thread),
+        });
+    }
+
+    /// Queues the records of one file. They reach each sink in order, though
+    /// possibly between the records of files uploaded at the same time.
+    pub fn session(&self, file: &str) -> ForwardSession<'_> {
+        ForwardSession {
+            file: file.into(),
+            queues: &self.queues,
+            dropped: vec![0; self.queues.len()],
+        }
+    }
+}
+
+impl Drop for Forwarder {
+    /// Sends the queued records before returning.
+    fn drop(&mut self) {
+        for queue in &mut self.queues {
+            queue.sender.take();
+        }
+        for queue in &mut self.queues {
+            if let Some(thread) = queue.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Writes the queued records to `sink` until the forwarder is dropped,
+/// flushing whenever the queue stays empty for a while.
+fn send_records(name: &str, mut sink: Sink, receiver: Receiver<Record>) {
+    let log_error = |file: Option<&str>, error| {
+        eprintln!(
+            "failed to forward {} to {}: {:#}",
+            file.unwrap_or("records"),
+            name,
+            anyhow::Error::from(error)
+        )
+    };
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(record) => {
+                let meta = RecordMeta {
+                    file: &record.file,
+                    index: record.index,
+                    offset: record.offset,
+                };
+                if let Err(error) = sink.write_record(&meta, &record.content) {
+                    log_error(Some(&record.file), error);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(error) = sink.flush() {
+                    log_error(None, error);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Err(error) = sink.finish() {
+                    log_error(None, error);
+                }
+                return;
+            }
+        }
+    }
+}
+
+pub struct ForwardSession<'a> {
+    file: Arc<str>,
+    queues: &'a [Queue],
+    /// Records dropped per queue because it was full.
+    dropped: Vec<usize>,
+}
+
+impl ForwardSession<'_> {
+    pub fn write_record(&mut self, meta: &RecordMeta, content: &str) {
+        for (queue, dropped) in self.queues.iter().zip(&mut self.dropped) {
+            let Some(sender) = &queue.sender else {
+                continue;
+            };
+            let record = Record {
+                file: self.file.clone(),
+                index: meta.index,
+                offset: meta.offset,
+                content: content.to_string(),
+            };
+            if sender.try_send(record).is_err() {
+                *dropped += 1;
+            }
+        }
+    }
+
+    /// Logs the records that were dropped.
+    pub fn finish(self) {
+        for (queue, dropped) in self.queues.iter().zip(&self.dropped) {
+            if *dropped > 0 {
+                eprintln!(
+                    "failed to forward {} records of {} to {}: queue full",
+                    dropped, self.file, queue.name
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Forwarder;
+    use glog_rust::{
+        export::{ExportError, RecordSink},
+        format::RecordMeta,
+    };
+    use std::sync::{mpsc, Arc, Mutex};
+
+    struct MemorySink {
+        records: Arc<Mutex<Vec<String>>>,
+        fail: bool,
+        /// Holds every record until a message arrives.
+        gate: Option<mpsc::Receiver<()>>,
+    }
+
+    impl RecordSink for MemorySink {
+        fn write_record(&mut self, _meta: &RecordMeta, content: &str) -> Result<(), ExportError> {
+            if let Some(gate) = &self.gate {
+                let _ = gate.recv();
+            }
+            if self.fail {
+                return Err(std::io::Error::other("unavailable").into());
+            }
+            self.records.lock().unwrap().push(content.to_string());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), ExportError> {
+            Ok(())
+        }
+    }
+
+    fn write(forwarder: &Forwarder, contents: &[&str]) -> Vec<usize> {
+        let mut session = forwarder.session("app.glog");
+        for (index, content) in contents.iter().enumerate() {
+            let meta = RecordMeta {
+                file: "app.glog",
+                index,
+                offset: 0,
+            };
+            session.write_record(&meta, content);
+        }
+        let dropped = session.dropped.clone();
+        session.finish();
+        dropped
+    }
+
+    #[test]
+    fn test_forward() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let mut forwarder = Forwarder::default();
+        forwarder.add(
+            "broken",
+            Box::new(MemorySink {
+                records: records.clone(),
+                fail: true,
+                gate: None,
+            }),
+        );
+        forwarder.add(
+            "memory",
+            Box::new(MemorySink {
+                records: records.clone(),
+                fail: false,
+                gate: None,
+            }),
+        );
+
+        assert_eq!(write(&forwarder, &["a", "b"]), [0, 0]);
+        drop(forwarder);
+        assert_eq!(*records.lock().unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_forward_queue_full() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let (open, gate) = mpsc::channel();
+        let mut forwarder = Forwarder::default().with_queue_capacity(2);
+        forwarder.add(
+            "stalled",
+            Box::new(MemorySink {
+                records: records.clone(),
+                fail: false,
+                gate: Some(gate),
+            }),
+        );
+
+        // the sink takes at most one record off the queue while it is stalled
+        let dropped = write(&forwarder, &["a", "b", "c", "d", "e"]);
+        assert!(dropped[0] == 2 || dropped[0] == 3, "{:?}", dropped);
+        for _ in 0..5 {
+            open.send(()).unwrap();
+        }
+        drop(forwarder);
+        assert_eq!(records.lock().unwrap().len(), 5 - dropped[0]);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::{error::ApiError, AppState};
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, FixedOffset};
+use glog_rust::{
+    entry::{filter::TimeBound, GlogEntry, Level},
+    format::{jsonl::json_record, RecordMeta},
+};
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection};
+use serde_json::{json, Value};
+use std::{collections::HashMap, path::Path, str::FromStr, sync::Arc, sync::Mutex};
+
+/// Key of the upload id in query results, next to the JSON Lines metadata.
+pub const UPLOAD_KEY: &str = "_upload";
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS records (
+    id INTEGER PRIMARY KEY,
+    upload_id TEXT NOT NULL,
+    file TEXT NOT NULL,
+    record_index INTEGER NOT NULL,
+    offset INTEGER NOT NULL,
+    sort_time INTEGER NOT NULL,
+    timestamp INTEGER,
+    level INTEGER,
+    user_id TEXT,
+    namespace TEXT,
+    content TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS records_time ON records (sort_time, id);
+CREATE INDEX IF NOT EXISTS records_user ON records (user_id, sort_time, id);
+CREATE INDEX IF NOT EXISTS records_namespace ON records (namespace, sort_time, id);
+";
+
+/// A decoded record waiting to be indexed.
+struct Row {
+    index: usize,
+    offset: i64,
+    sort_time: i64,
+    entry: Option<GlogEntry>,
+    content: String,
+}
+
+/// The records of one upload, indexed together.
+pub struct RecordBatch {
+    upload_id: String,
+    file: String,
+    /// Sort time of records without a timestamp of their own.
+    last_time: i64,
+    rows: Vec<Row>,
+}
+
+impl RecordBatch {
+    /// `ingested_at` is the sort time, in Unix milliseconds, of records
+    /// before the first one with a timestamp.
+    pub fn new(upload_id: &str, file: &str, ingested_at: i64) -> Self {
+        Self {
+            upload_id: upload_id.to_string(),
+            file: file.to_string(),
+            last_time: ingested_at,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Records without a timestamp sort with the record before them, like
+    /// in a merge.
+    pub fn push(&mut self, meta: &RecordMeta, content: &str) {
+        let entry = GlogEntry::parse(content);
+        if let Some(timestamp) = entry.as_ref().and_then(|entry| entry.timestamp) {
+            self.last_time = timestamp.timestamp_millis();
+        }
+        self.rows.push(Row {
+            index: meta.index,
+            offset: meta.offset,
+            sort_time: self.last_time,
+            entry,
+            content: content.to_string(),
+        });
+    }
+}
+
+/// Position after the last record of a page, as `<sort time>-<id>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    sort_time: i64,
+    id: i64,
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cursor: {}", s);
+        let (sort_time, id) = s.rsplit_once('-').ok_or_else(invalid)?;
+        Ok(Cursor {
+            sort_time: sort_time.parse().map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.sort_time, self.id)
+    }
+}
+
+/// Conditions of a search; records are returned in time order.
+#[derive(Debug, Default)]
+pub struct RecordQuery {
+    pub user_id: Option<String>,
+    pub namespace: Option<String>,
+    pub min_level: Option<Level>,
+    pub since: Option<DateTime<FixedOffset>>,
+    pub until: Option<DateTime<FixedOffset>>,
+    pub after: Option<Cursor>,
+    pub limit: usize,
+}
+
+impl RecordQuery {
+    /// Parses `user`, `namespace`, `level`, `since`, `until`, `cursor` and
+    /// `limit` query parameters.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, ApiError> {
+        fn parse<T: FromStr<Err = String>>(value: Option<&String>) -> Result<Option<T>, ApiError> {
+            value
+                .map(|value| value.parse().map_err(ApiError::bad_request))
+                .transpose()
+        }
+
+        fn time(value: Option<&String>) -> Result<Option<DateTime<FixedOffset>>, ApiError> {
+            match parse(value)? {
+                None => Ok(None),
+                Some(TimeBound::At(at)) => Ok(Some(at)),
+                Some(TimeBound::TimeOfDay(_)) => {
+                    Err(ApiError::bad_request("time bounds need a date"))
+                }
+            }
+        }
+
+        let limit = match params.get("limit") {
+            None => DEFAULT_PAGE_SIZE,
+            Some(limit) => match limit.parse() {
+                Ok(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => limit,
+                _ => {
+                    return Err(ApiError::bad_request(format!(
+                        "limit must be between 1 and {}",
+                        MAX_PAGE_SIZE
+                    )))
+                }
+            },
+        };
+
+        Ok(RecordQuery {
+            user_id: params.get("user").cloned(),
+            namespace: params.get("namespace").cloned(),
+            min_level: parse(params.get("level"))?,
+            since: time(params.get("since"))?,
+            until: time(params.get("until"))?,
+            after: parse(params.get("cursor"))?,
+            limit,
+        })
+    }
+}
+
+/// One page of results, with the cursor of the next page if there is one.
+pub struct Page {
+    pub records: Vec<Value>,
+    pub next: Option<Cursor>,
+}
+
+/// SQLite index of every decoded record, searched by the query endpoint.
+pub struct Index {
+    connection: Mutex<Connection>,
+}
+
+impl Index {
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn insert(&self, batch: &RecordBatch) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO records (upload_id, file, record_index, offset, sort_time,
+                    timestamp, level, user_id, namespace, content)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for row in &batch.rows {
+                let entry = row.entry.as_ref();
+                statement.execute(params![
+                    batch.upload_id,
+                    batch.file,
+                    row.index,
+                    row.offset,
+                    row.sort_time,
+                    entry
+                        .and_then(|entry| entry.timestamp)
+                        .map(|timestamp| timestamp.timestamp_millis()),
+                    entry
+                        .and_then(|entry| entry.level)
+                        .map(|level| level as i64),
+                    entry.and_then(|entry| entry.user_id.as_deref()),
+                    entry.and_then(|entry| entry.namespace.as_deref()),
+                    row.content,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn search(&self, query: &RecordQuery) -> Result<Page> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+
+        if let Some(user_id) = &query.user_id {
+            conditions.push("user_id = ?");
+            values.push(SqlValue::Text(user_id.clone()));
+        }
+        if let Some(namespace) = &query.namespace {
+            conditions.push("namespace = ?");
+            values.push(SqlValue::Text(namespace.clone()));
+        }
+        if let Some(level) = query.min_level {
+            conditions.push("level >= ?");
+            values.push(SqlValue::Integer(level as i64));
+        }
+        if let Some(since) = query.since {
+            conditions.push("timestamp >= ?");
+            values.push(SqlValue::Integer(since.timestamp_millis()));
+        }
+        if let Some(until) = query.until {
+            conditions.push("timestamp < ?");
+            values.push(SqlValue::Integer(until.timestamp_millis()));
+        }
+        if let Some(after) = query.after {
+            conditions.push("(sort_time > ? OR (sort_time = ? AND id > ?))");
+            values.push(SqlValue::Integer(after.sort_time));
+            values.push(SqlValue::Integer(after.sort_time));
+            values.push(SqlValue::Integer(after.id));
+        }
+
+        let mut sql = "SELECT id, sort_time, upload_id, file, record_index, offset, content
+             FROM records"
+            .to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        // one extra row tells whether there is a next page
+        sql.push_str(" ORDER BY sort_time, id LIMIT ?");
+        values.push(SqlValue::Integer(query.limit as i64 + 1));
+
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(&sql)?;
+        let mut rows = statement.query(params_from_iter(values))?;
+
+        let mut records = Vec::new();
+        let mut last = None;
+        while let Some(row) = rows.next()? {
+            if records.len() == query.limit {
+                return Ok(Page {
+                    records,
+                    next: last,
+                });
+            }
+
+            let upload_id: String = row.get(2)?;
+            let file: String = row.get(3)?;
+            let content: String = row.get(6)?;
+            let meta = RecordMeta {
+                file: &file,
+                index: row.get(4)?,
+                offset: row.get(5)?,
+            };
+            let mut record = json_record(&meta, &content);
+            record.insert(UPLOAD_KEY.to_string(), upload_id.into());
+            records.push(Value::Object(record));
+
+            last = Some(Cursor {
+                sort_time: row.get(1)?,
+                id: row.get(0)?,
+            });
+        }
+
+        Ok(Page {
+            records,
+            next: None,
+        })
+    }
+}
+
+/// `GET /query`: one page of matching records as
+/// `{"records": [...], "next": cursor}`.
+pub async fn query(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Value>, ApiError> {
+    let query = RecordQuery::from_params(&params)?;
+    let page = tokio::task::spawn_blocking(move || state.index.search(&query))
+        .await
+        .map_err(anyhow::Error::from)??;
+
+    Ok(Json(json!({
+        "records": page.records,
+        "next": page.next.map(|cursor| cursor.to_string()),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Index, RecordBatch, RecordQuery};
+    use anyhow::Result;
+    use glog_rust::{
+        entry::{parse_timestamp, Level},
+        format::RecordMeta,
+    };
+
+    #[test]
+    fn test_search() -> Result<()> {
+        let index = Index::open(":memory:".as_ref())?;
+
+        let log = |user: &str, level: u8, second: u32| {
+            format!(
+                r#"{{"msg":"{}{}","level":"{}","timestamp":"2023-08-03 08:00:{:02} +0000","userId":"{}"}}"#,
+                user, second, level, second, user
+            )
+        };
+        let logs = [
+            log("a", 2, 1),
+            "plain text".to_string(),
+            log("b", 4, 2),
+            log("a", 4, 3),
+            log("a", 3, 5),
+        ];
+        let mut batch = RecordBatch::new("upload1", "app.glog", 0);
+        for (index, content) in logs.iter().enumerate() {
+            let meta = RecordMeta {
+                file: "app.glog",
+                index,
+                offset: index as i64 * 100,
+            };
+            batch.push(&meta, content);
+        }
+        // a later upload with older records sorts before them
+        let mut older = RecordBatch::new("upload2", "old.glog", 0);
+        let meta = RecordMeta {
+            file: "old.glog",
+            index: 0,
+            offset: 28,
+        };
+        older.push(&meta, &log("a", 5, 0));
+        index.insert(&batch)?;
+        index.insert(&older)?;
+
+        let messages = |query: &RecordQuery| -> Result<Vec<String>> {
+            let page = index.search(query)?;
+            Ok(page
+                .records
+                .iter()
+                .map(|record| {
+                    record
+                        .get("msg")
+                        .or(record.get("raw"))
+                        .and_then(|value| value.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect())
+        };
+
+        let all = RecordQuery {
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            messages(&all)?,
+            ["a0", "a1", "plain text", "b2", "a3", "a5"]
+        );
+
+        let query = RecordQuery {
+            user_id: Some("a".to_string()),
+            min_level: Some(Level::Warn),
+            limit: 2,
+            ..Default::default()
+        };
+        let page = index.search(&query)?;
+        assert_eq!(page.records[0]["_upload"], "upload2");
+        assert_eq!(page.records[1]["_file"], "app.glog");
+        assert_eq!(page.records[1]["_index"], 3);
+        let next = RecordQuery {
+            after: page.next,
+            ..query
+        };
+        assert_eq!(messages(&next)?, ["a5"]);
+        assert!(index.search(&next)?.next.is_none());
+
+        let range = RecordQuery {
+            since: parse_timestamp("2023-08-03 08:00:02 +0000"),
+            until: parse_timestamp("2023-08-03 08:00:05 +0000"),
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(messages(&range)?, ["b2", "a3"]);
+
+        Ok(())
+    }
+}
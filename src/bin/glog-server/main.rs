@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{get, post},
+    Router,
+};
+use clap::Parser;
+use glog_rust::{
+    cipher::{aes_cfb_ecdh::Cipher, key_file::parse_private_key, key_pair::Curve},
+    export::{elasticsearch, loki, otlp},
+    io::{codec::CodecRegistry, keyring::Keyring},
+};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+mod error;
+mod forward;
+mod index;
+mod metrics;
+mod tail;
+mod upload;
+
+#[derive(Parser)]
+#[command(
+    name = "glog-server",
+    version,
+    about = "HTTP server that receives and decodes glog uploads"
+)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "0.0.0.0:8080")]
+    listen: SocketAddr,
+
+    /// Directory for raw uploads and decoded records
+    #[arg(long, default_value = "glog-data")]
+    data_dir: PathBuf,
+
+    /// Server private key: hex, a key file or a directory of key files; may
+    /// be repeated to accept files written for any of the keys
+    #[arg(short, long = "key", env = "PRI_KEY", hide_env_values = true)]
+    keys: Vec<String>,
+
+    /// Curve of the server keys, ignored for PEM keys
+    #[arg(long, default_value_t = Curve::Secp256k1)]
+    curve: Curve,
+
+    /// Largest accepted request body, in bytes
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    max_upload_size: usize,
+
+    /// Dictionary used by zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+
+    /// Also send decoded records to this OTLP/HTTP logs URL
+    #[arg(long, value_name = "URL")]
+    forward_otlp: Option<String>,
+
+    /// Also send decoded records to this Loki push URL
+    #[arg(long, value_name = "URL")]
+    forward_loki: Option<String>,
+
+    /// Also index decoded records in this Elasticsearch cluster
+    #[arg(long, value_name = "URL")]
+    forward_elasticsearch: Option<String>,
+
+    /// Elasticsearch index of forwarded records
+    #[arg(long, default_value = elasticsearch::DEFAULT_INDEX)]
+    elasticsearch_index: String,
+}
+
+/// Shared by all requests.
+pub struct AppState {
+    pub keyring: Keyring,
+    pub registry: CodecRegistry,
+    pub storage: upload::Storage,
+    pub index: index::Index,
+    pub feed: tail::LiveFeed,
+    pub metrics: Arc<metrics::ServerMetrics>,
+    pub forwarder: forward::Forwarder,
+}
+
+fn load_keyring(keys: &[String], curve: Curve) -> Result<Keyring> {
+    let mut keyring = Keyring::new();
+    for (i, key) in keys.iter().enumerate() {
+        let path = Path::new(key);
+        if path.exists() {
+            keyring
+                .add_path(path, curve)
+                .with_context(|| format!("failed to load key {}", path.display()))?;
+        } else {
+            let key_pair = parse_private_key(key, curve)?;
+            let cipher = Cipher::new_with_curve(&key_pair.private_key, key_pair.curve())?;
+            keyring.add(format!("key{}", i), cipher);
+        }
+    }
+    Ok(keyring)
+}
+
+fn codec_registry(zstd_dict: Option<&Path>) -> Result<CodecRegistry> {
+    let mut registry = CodecRegistry::default();
+    if let Some(path) = zstd_dict {
+        registry = registry.with_zstd(zstd::DEFAULT_COMPRESSION_LEVEL, &std::fs::read(path)?);
+    }
+    Ok(registry)
+}
+
+fn forwarder(args: &Args) -> forward::Forwarder {
+    let mut forwarder = forward::Forwarder::default();
+    if let Some(url) = &args.forward_otlp {
+        forwarder.add(url, Box::new(otlp::OtlpExporter::new(url)));
+    }
+    if let Some(url) = &args.forward_loki {
+        forwarder.add(url, Box::new(loki::LokiSink::new(url)));
+    }
+    if let Some(url) = &args.forward_elasticsearch {
+        let sink = elasticsearch::ElasticsearchSink::new(url).with_index(&args.elasticsearch_index);
+        forwarder.add(url, Box::new(sink));
+    }
+    forwarder
+}
+
+pub fn router(state: Arc<AppState>, max_upload_size: usize) -> Router {
+    Router::new()
+        .route("/upload", post(upload::upload))
+        .route("/query", get(index::query))
+        .route("/tail", get(tail::tail))
+        .route("/metrics", get(metrics::metrics))
+        .layer(DefaultBodyLimit::max(max_upload_size))
+        .with_state(state)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let keyring = load_keyring(&args.keys, args.curve)?;
+    if keyring.is_empty() {
+        eprintln!("warning: no key given, encrypted uploads will fail to decode");
+    }
+
+    let state = Arc::new(AppState {
+        keyring,
+        registry: codec_registry(args.zstd_dict.as_deref())?,
+        storage: upload::Storage::open(&args.data_dir)?,
+        index: index::Index::open(&args.data_dir.join("index.sqlite"))?,
+        feed: tail::LiveFeed::default(),
+        metrics: Arc::new(metrics::ServerMetrics::new()?),
+        forwarder: forwarder(&args),
+    });
+
+    println!("listening on {}", args.listen);
+    axum::Server::bind(&args.listen)
+        .serve(router(state, args.max_upload_size).into_make_service())
+        .await?;
+    Ok(())
+}
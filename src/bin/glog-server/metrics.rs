@@ -0,0 +1,123 @@
+use crate::{error::ApiError, AppState};
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+};
+use glog_rust::io::metrics::{DecodeFailure, DecodeMetrics};
+use prometheus::{
+    core::Collector, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+use std::{sync::Arc, time::Duration};
+
+/// Decoding and upload metrics in the Prometheus text format.
+pub struct ServerMetrics {
+    registry: Registry,
+    records: IntCounter,
+    bytes_in: IntCounter,
+    bytes_out: IntCounter,
+    failures: IntCounterVec,
+    recovery_skips: IntCounter,
+    file_seconds: Histogram,
+    uploads: IntCounterVec,
+}
+
+impl ServerMetrics {
+    pub fn new() -> Result<Self> {
+        let metrics = Self {
+            registry: Registry::new(),
+            records: IntCounter::new("glog_records_decoded_total", "Records decoded")?,
+            bytes_in: IntCounter::new(
+                "glog_decode_bytes_in_total",
+                "Record payload bytes read, still compressed",
+            )?,
+            bytes_out: IntCounter::new(
+                "glog_decode_bytes_out_total",
+                "Record content bytes produced",
+            )?,
+            failures: IntCounterVec::new(
+                Opts::new("glog_decode_failures_total", "Decode failures by cause"),
+                &["cause"],
+            )?,
+            recovery_skips: IntCounter::new(
+                "glog_recovery_skips_total",
+                "Records read past a damaged sync marker",
+            )?,
+            file_seconds: Histogram::with_opts(HistogramOpts::new(
+                "glog_file_decode_seconds",
+                "Time to decode one uploaded file",
+            ))?,
+            uploads: IntCounterVec::new(
+                Opts::new("glog_uploaded_files_total", "Uploaded files by outcome"),
+                &["status"],
+            )?,
+        };
+
+        let collectors: [Box<dyn Collector>; 7] = [
+            Box::new(metrics.records.clone()),
+            Box::new(metrics.bytes_in.clone()),
+            Box::new(metrics.bytes_out.clone()),
+            Box::new(metrics.failures.clone()),
+            Box::new(metrics.recovery_skips.clone()),
+            Box::new(metrics.file_seconds.clone()),
+            Box::new(metrics.uploads.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector)?;
+        }
+
+        // export every cause and status from the start, not only once seen
+        for failure in [
+            DecodeFailure::Decrypt,
+            DecodeFailure::Decompress,
+            DecodeFailure::InvalidSyncMarker,
+            DecodeFailure::Other,
+        ] {
+            metrics.failures.with_label_values(&[failure.as_str()]);
+        }
+        for status in ["ok", "failed"] {
+            metrics.uploads.with_label_values(&[status]);
+        }
+
+        Ok(metrics)
+    }
+
+    pub fn file_uploaded(&self, ok: bool) {
+        let status = if ok { "ok" } else { "failed" };
+        self.uploads.with_label_values(&[status]).inc();
+    }
+
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+impl DecodeMetrics for ServerMetrics {
+    fn record_decoded(&self, bytes_in: usize, bytes_out: usize) {
+        self.records.inc();
+        self.bytes_in.inc_by(bytes_in as u64);
+        self.bytes_out.inc_by(bytes_out as u64);
+    }
+
+    fn decode_failed(&self, failure: DecodeFailure) {
+        self.failures.with_label_values(&[failure.as_str()]).inc();
+    }
+
+    fn recovery_skip(&self, _offset: i64) {
+        self.recovery_skips.inc();
+    }
+
+    fn file_decoded(&self, elapsed: Duration) {
+        self.file_seconds.observe(elapsed.as_secs_f64());
+    }
+}
+
+/// `GET /metrics`
+pub async fn metrics(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    let text = state.metrics.encode()?;
+    Ok(([(CONTENT_TYPE, TextEncoder::new().format_type())], text).into_response())
+}
@@ -0,0 +1,146 @@
+use crate::{error::ApiError, index::UPLOAD_KEY, AppState};
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{stream, Stream};
+use glog_rust::{
+    entry::{filter::EntryFilter, GlogEntry},
+    format::{jsonl::json_record, RecordMeta},
+};
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Records buffered per subscriber before it starts missing records.
+const FEED_CAPACITY: usize = 4096;
+
+/// A decoded record as sent to live subscribers.
+pub struct LiveRecord {
+    entry: Option<GlogEntry>,
+    /// The record as a JSON Lines object, like in query results.
+    json: String,
+}
+
+impl LiveRecord {
+    fn matches(&self, filter: &EntryFilter) -> bool {
+        filter.is_empty()
+            || self
+                .entry
+                .as_ref()
+                .is_some_and(|entry| filter.matches(entry))
+    }
+}
+
+/// Fans out the records of every upload, as they are decoded, to the
+/// subscribers of the live tail.
+pub struct LiveFeed {
+    sender: broadcast::Sender<Arc<LiveRecord>>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(FEED_CAPACITY).0,
+        }
+    }
+}
+
+impl LiveFeed {
+    /// Does nothing while nobody is subscribed.
+    pub fn publish(&self, upload_id: &str, meta: &RecordMeta, content: &str) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+
+        let mut object = json_record(meta, content);
+        object.insert(UPLOAD_KEY.to_string(), upload_id.into());
+        let record = LiveRecord {
+            entry: GlogEntry::parse(content),
+            json: serde_json::Value::Object(object).to_string(),
+        };
+        // fails only when the last subscriber just left
+        let _ = self.sender.send(Arc::new(record));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveRecord>> {
+        self.sender.subscribe()
+    }
+}
+
+/// Waits for the next record that passes `filter`. Returns how many records
+/// were dropped instead when the subscriber fell behind, and `None` once the
+/// feed is gone.
+async fn next_record(
+    receiver: &mut broadcast::Receiver<Arc<LiveRecord>>,
+    filter: &EntryFilter,
+) -> Option<Result<Arc<LiveRecord>, u64>> {
+    loop {
+        match receiver.recv().await {
+            Ok(record) if record.matches(filter) => return Some(Ok(record)),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => return Some(Err(skipped)),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// `GET /tail`: Server-Sent Events with a `record` event per decoded record
+/// that matches the `user`, `namespace` and `level` query parameters, and a
+/// `lagged` event with the number of records missed by a slow client.
+pub async fn tail(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let mut filter = EntryFilter::new();
+    if let Some(user_id) = params.get("user") {
+        filter = filter.with_user_id(user_id);
+    }
+    if let Some(namespace) = params.get("namespace") {
+        filter = filter.with_namespace(namespace);
+    }
+    if let Some(level) = params.get("level") {
+        filter = filter.with_min_level(level.parse().map_err(ApiError::bad_request)?);
+    }
+
+    let receiver = state.feed.subscribe();
+    let events = stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        let event = match next_record(&mut receiver, &filter).await? {
+            Ok(record) => Event::default().event("record").data(&record.json),
+            Err(skipped) => Event::default().event("lagged").data(skipped.to_string()),
+        };
+        Some((Ok(event), (receiver, filter)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_record, LiveFeed};
+    use glog_rust::{entry::filter::EntryFilter, format::RecordMeta};
+
+    #[tokio::test]
+    async fn test_live_feed() {
+        let feed = LiveFeed::default();
+        let meta = RecordMeta {
+            file: "app.glog",
+            index: 0,
+            offset: 28,
+        };
+        // nobody is listening yet
+        feed.publish("upload0", &meta, r#"{"msg":"lost","userId":"u1"}"#);
+
+        let mut receiver = feed.subscribe();
+        let filter = EntryFilter::new().with_user_id("u1");
+        feed.publish("upload1", &meta, r#"{"msg":"other","userId":"u2"}"#);
+        feed.publish("upload1", &meta, "plain text");
+        feed.publish("upload1", &meta, r#"{"msg":"mine","userId":"u1"}"#);
+        drop(feed);
+
+        let record = next_record(&mut receiver, &filter).await.unwrap().unwrap();
+        assert_eq!(
+            record.json,
+            r#"{"_file":"app.glog","_index":0,"_offset":28,"msg":"mine","userId":"u1","_upload":"upload1"}"#
+        );
+        assert!(next_record(&mut receiver, &filter).await.is_none());
+    }
+}
@@ -0,0 +1,426 @@
+use crate::{error::ApiError, index::RecordBatch, AppState};
+use anyhow::{Context, Result};
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Multipart, Query, State},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use glog_rust::{
+    cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+    format::{jsonl::JsonlFormat, RecordFormat, RecordMeta},
+    io::{
+        log_reader::LogBufReaderV4,
+        metrics::{DecodeFailure, DecodeMetrics},
+        primitive::{EncryptMode, SINGLE_LOG_CONTENT_MAX_LENGTH},
+    },
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Name of a raw upload that does not come with one.
+const DEFAULT_UPLOAD_NAME: &str = "upload.glog";
+
+/// Keeps every upload under `raw/<id>.glog` and its decoded records under
+/// `decoded/<id>.jsonl`.
+pub struct Storage {
+    raw_dir: PathBuf,
+    decoded_dir: PathBuf,
+}
+
+impl Storage {
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        let storage = Self {
+            raw_dir: data_dir.join("raw"),
+            decoded_dir: data_dir.join("decoded"),
+        };
+        for dir in [&storage.raw_dir, &storage.decoded_dir] {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        Ok(storage)
+    }
+
+    /// Unique and sortable by upload time.
+    fn new_id(millis: i64) -> String {
+        format!("{}-{:08x}", millis, rand::random::<u32>())
+    }
+
+    pub fn raw_path(&self, id: &str) -> PathBuf {
+        self.raw_dir.join(format!("{}.glog", id))
+    }
+
+    pub fn decoded_path(&self, id: &str) -> PathBuf {
+        self.decoded_dir.join(format!("{}.jsonl", id))
+    }
+}
+
+/// Outcome of one uploaded file.
+#[derive(Debug)]
+pub struct FileReport {
+    pub name: String,
+    pub id: String,
+    pub bytes: usize,
+    /// Records decoded, also when decoding failed later on.
+    pub records: usize,
+    /// Name of the keyring key that decoded the file.
+    pub key: Option<String>,
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "id": self.id,
+            "status": if self.error.is_none() { "ok" } else { "failed" },
+            "bytes": self.bytes,
+            "records": self.records,
+            "key": self.key,
+            "error": self.error,
+        })
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// Stores `data`, decodes it with the matching keyring key, and stores,
+/// indexes and forwards the records. Decoding errors end up in the report; only storage
+/// errors are returned.
+pub fn ingest(state: &AppState, name: &str, data: &[u8]) -> Result<FileReport> {
+    let ingested_at = now_millis();
+    let id = Storage::new_id(ingested_at);
+    std::fs::write(state.storage.raw_path(&id), data)?;
+
+    let mut output = BufWriter::new(File::create(state.storage.decoded_path(&id))?);
+    let mut batch = RecordBatch::new(&id, name, ingested_at);
+    let mut report = FileReport {
+        name: name.to_string(),
+        id: id.clone(),
+        bytes: data.len(),
+        records: 0,
+        key: None,
+        error: None,
+    };
+
+    let mut format = JsonlFormat;
+    let mut forward = state.forwarder.session(name);
+    let start = Instant::now();
+    let result = decode(state, data, &mut report, &mut |meta, content| {
+        format.write_record(&mut output, meta, content)?;
+        batch.push(meta, content);
+        state.feed.publish(&id, meta, content);
+        forward.write_record(meta, content);
+        Ok(())
+    });
+    state.metrics.file_decoded(start.elapsed());
+    forward.finish();
+    output.flush()?;
+    state.index.insert(&batch)?;
+
+    if let Err(error) = result {
+        if error.is::<std::io::Error>() {
+            return Err(error);
+        }
+        report.error = Some(format!("{:#}", error));
+    }
+    state.metrics.file_uploaded(report.error.is_none());
+    Ok(report)
+}
+
+fn decode(
+    state: &AppState,
+    data: &[u8],
+    report: &mut FileReport,
+    on_record: &mut dyn FnMut(&RecordMeta, &str) -> std::io::Result<()>,
+) -> Result<()> {
+    let placeholder;
+    let cipher = match state.keyring.find(data, &state.registry) {
+        Some((key, cipher)) => {
+            report.key = Some(key.to_string());
+            cipher
+        }
+        None => {
+            placeholder = Cipher::new(&KeyPair::random()?.private_key)?;
+            &placeholder
+        }
+    };
+
+    let mut reader = LogBufReaderV4::new(data, cipher)
+        .with_registry(state.registry.clone())
+        .with_metrics(state.metrics.clone());
+    reader.read_header()?;
+
+    let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+    while let Some(record) = reader.read_record()? {
+        if record.mode.encrypt != EncryptMode::None && report.key.is_none() {
+            state.metrics.decode_failed(DecodeFailure::Decrypt);
+            anyhow::bail!(
+                "no key decodes the encrypted record at offset {}",
+                record.offset
+            );
+        }
+
+        buffer.clear();
+        reader.inflate_record(&record, &mut buffer)?;
+
+        let meta = RecordMeta {
+            file: &report.name,
+            index: report.records,
+            offset: record.offset,
+        };
+        on_record(&meta, &String::from_utf8_lossy(&buffer))?;
+        report.records += 1;
+    }
+    Ok(())
+}
+
+fn is_multipart(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"))
+}
+
+/// Collects the uploaded files: every file field of a multipart form, or the
+/// whole body named by the `name` query parameter.
+async fn read_files(
+    state: &Arc<AppState>,
+    query: &HashMap<String, String>,
+    request: Request<Body>,
+) -> Result<Vec<(String, Bytes)>, ApiError> {
+    if !is_multipart(&request) {
+        let data = Bytes::from_request(request, state)
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        if data.is_empty() {
+            return Err(ApiError::bad_request("empty request body"));
+        }
+        let name = query
+            .get("name")
+            .map_or(DEFAULT_UPLOAD_NAME, String::as_str);
+        return Ok(vec![(name.to_string(), data)]);
+    }
+
+    let mut multipart = Multipart::from_request(request, state)
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+    let mut files = Vec::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))?
+    {
+        let name = match (field.file_name(), field.name()) {
+            (Some(file_name), _) => file_name.to_string(),
+            (None, Some("file")) => DEFAULT_UPLOAD_NAME.to_string(),
+            _ => continue,
+        };
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        files.push((name, data));
+    }
+
+    if files.is_empty() {
+        return Err(ApiError::bad_request("no files in upload"));
+    }
+    Ok(files)
+}
+
+/// `POST /upload`: answers 200 with a report per file when all files
+/// decoded, 422 with the same reports when any did not.
+pub async fn upload(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> Result<Response, ApiError> {
+    let files = read_files(&state, &query, request).await?;
+
+    let reports = tokio::task::spawn_blocking(move || {
+        files
+            .iter()
+            .map(|(name, data)| ingest(&state, name, data))
+            .collect::<Result<Vec<_>>>()
+    })
+    .await
+    .map_err(anyhow::Error::from)??;
+
+    let status = if reports.iter().all(|report| report.error.is_none()) {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    let reports: Vec<_> = reports.iter().map(FileReport::to_json).collect();
+    Ok((status, Json(json!({ "files": reports }))).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ingest, Storage};
+    use crate::{
+        forward::Forwarder, index::Index, metrics::ServerMetrics, router, tail::LiveFeed, AppState,
+    };
+    use anyhow::Result;
+    use axum::{
+        body::{Body, HttpBody},
+        http::{header::CONTENT_TYPE, Request, StatusCode},
+    };
+    use glog_rust::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            codec::CodecRegistry,
+            keyring::Keyring,
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use std::{path::Path, sync::Arc};
+    use tower::ServiceExt;
+
+    fn state(dir: &Path, keyring: Keyring) -> Result<AppState> {
+        Ok(AppState {
+            keyring,
+            registry: CodecRegistry::default(),
+            storage: Storage::open(dir)?,
+            index: Index::open(":memory:".as_ref())?,
+            feed: LiveFeed::default(),
+            metrics: Arc::new(ServerMetrics::new()?),
+            forwarder: Forwarder::default(),
+        })
+    }
+
+    /// A file with two zlib-compressed records encrypted for `server_key`.
+    fn encrypted_file(server_key: &KeyPair) -> Result<Vec<u8>> {
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        for log in [r#"{"msg":"a"}"#, r#"{"msg":"b"}"#] {
+            writer.write_single_log(
+                (CompressMode::Zlib, EncryptMode::Aes),
+                &server_key.public_key,
+                log,
+            )?;
+        }
+        drop(writer);
+        Ok(file)
+    }
+
+    #[test]
+    fn test_ingest() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-server-{}", std::process::id()));
+        let server_key = KeyPair::random()?;
+        let mut keyring = Keyring::new();
+        keyring.add("server", Cipher::new(&server_key.private_key)?);
+        let state = state(&dir, keyring)?;
+
+        let file = encrypted_file(&server_key)?;
+
+        let report = ingest(&state, "app.glog", &file)?;
+        assert_eq!(report.records, 2);
+        assert_eq!(report.key.as_deref(), Some("server"));
+        assert!(report.error.is_none());
+        assert_eq!(std::fs::read(state.storage.raw_path(&report.id))?, file);
+        let decoded = std::fs::read_to_string(state.storage.decoded_path(&report.id))?;
+        assert_eq!(
+            decoded.lines().next(),
+            Some(r#"{"_file":"app.glog","_index":0,"_offset":28,"msg":"a"}"#)
+        );
+
+        let report = ingest(&state, "broken.glog", b"not a glog file")?;
+        assert_eq!(report.records, 0);
+        assert!(report.error.is_some());
+
+        let metrics = state.metrics.encode()?;
+        assert!(metrics.contains("glog_records_decoded_total 2\n"));
+        assert!(metrics.contains("glog_decode_failures_total{cause=\"other\"} 1\n"));
+        assert!(metrics.contains("glog_uploaded_files_total{status=\"failed\"} 1\n"));
+        assert!(metrics.contains("glog_file_decode_seconds_count 2\n"));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_errors() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-server-upload-{}", std::process::id()));
+        let app = router(Arc::new(state(&dir, Keyring::new())?), 1 << 20);
+        let upload = |content_type: &str, body: Vec<u8>| {
+            Request::post("/upload")
+                .header(CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+        };
+
+        let cases = vec![
+            (
+                upload("application/octet-stream", Vec::new())?,
+                StatusCode::BAD_REQUEST,
+                "empty request body",
+            ),
+            // no boundary
+            (
+                upload("multipart/form-data", b"--x\r\n".to_vec())?,
+                StatusCode::BAD_REQUEST,
+                "",
+            ),
+            (
+                upload(
+                    "multipart/form-data; boundary=x",
+                    b"--x\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\ntruncated"
+                        .to_vec(),
+                )?,
+                StatusCode::BAD_REQUEST,
+                "",
+            ),
+            (
+                upload(
+                    "multipart/form-data; boundary=x",
+                    b"--x\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n--x--\r\n"
+                        .to_vec(),
+                )?,
+                StatusCode::BAD_REQUEST,
+                "no files in upload",
+            ),
+            // the keyring has no key for the file
+            (
+                upload(
+                    "application/octet-stream",
+                    encrypted_file(&KeyPair::random()?)?,
+                )?,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "no key decodes the encrypted record at offset 28",
+            ),
+        ];
+
+        for (request, status, message) in cases {
+            let response = app.clone().oneshot(request).await?;
+            assert_eq!(response.status(), status);
+            let mut body = response.into_body();
+            let mut text = Vec::new();
+            while let Some(chunk) = body.data().await {
+                text.extend_from_slice(&chunk?);
+            }
+            let text = String::from_utf8(text)?;
+            assert!(text.contains(message), "{}", text);
+        }
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    key::{placeholder_cipher, KeyArgs},
+    Failure,
+};
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use glog_rust::{
+    cipher::aes_cfb_ecdh::Cipher,
+    entry::{
+        filter::{EntryFilter, TimeBound},
+        Level,
+    },
+    format::{
+        csv::CsvFormat, jsonl::JsonlFormat, logfmt::LogfmtFormat, RecordFormat, RecordMeta,
+        TextFormat,
+    },
+    io::{
+        codec::CodecRegistry,
+        input::{for_each_glog, InputError},
+        log_reader::LogBufReaderV4,
+        primitive::{EncryptMode, SINGLE_LOG_CONTENT_MAX_LENGTH},
+    },
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// Record content, one per line
+    Text,
+    /// JSON Lines with the file name, record index and offset
+    Jsonl,
+    /// Comma-separated values with a header row, see --columns
+    Csv,
+    /// logfmt key=value pairs, one record per line
+    Logfmt,
+}
+
+#[derive(Args)]
+pub struct DecodeArgs {
+    /// Glog files, directories, glob patterns or .zip/.tar.gz archives to
+    /// decode, in order
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Dictionary used by zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Write records to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// CSV columns, by JSON Lines key [default: _file,_offset,timestamp,level,userId,namespace,msg,raw]
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+}
+
+impl OutputArgs {
+    pub fn open(&self) -> Result<Box<dyn Write>> {
+        Ok(match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(io::stdout().lock())),
+        })
+    }
+
+    pub fn record_format(&self) -> Box<dyn RecordFormat> {
+        match self.format {
+            OutputFormat::Text => Box::new(TextFormat),
+            OutputFormat::Jsonl => Box::new(JsonlFormat),
+            OutputFormat::Csv if self.columns.is_empty() => Box::new(CsvFormat::default()),
+            OutputFormat::Csv => Box::new(CsvFormat::new(self.columns.clone())),
+            OutputFormat::Logfmt => Box::new(LogfmtFormat),
+        }
+    }
+}
+
+/// Default codecs, with zstd records using the dictionary in `zstd_dict`.
+pub fn codec_registry(zstd_dict: Option<&Path>) -> Result<CodecRegistry> {
+    let mut registry = CodecRegistry::default();
+    if let Some(path) = zstd_dict {
+        registry = registry.with_zstd(zstd::DEFAULT_COMPRESSION_LEVEL, &std::fs::read(path)?);
+    }
+    Ok(registry)
+}
+
+/// Conditions on the standard JSON fields; records failing any are skipped.
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Minimum level, by name (debug, info, warn, ...) or number
+    #[arg(long)]
+    level: Option<Level>,
+
+    /// Keep records at or after this time, e.g. "2023-08-03 08:00" (UTC) or
+    /// "08:00" for a time of day
+    #[arg(long)]
+    since: Option<TimeBound>,
+
+    /// Keep records before this time
+    #[arg(long)]
+    until: Option<TimeBound>,
+
+    /// Keep records of this userId, may be repeated
+    #[arg(long = "user")]
+    users: Vec<String>,
+
+    /// Keep records of this namespace, may be repeated
+    #[arg(long = "namespace")]
+    namespaces: Vec<String>,
+}
+
+impl FilterArgs {
+    pub fn to_filter(&self) -> EntryFilter {
+        let mut filter = EntryFilter::new();
+        if let Some(level) = self.level {
+            filter = filter.with_min_level(level);
+        }
+        if let Some(since) = self.since {
+            filter = filter.with_since(since);
+        }
+        if let Some(until) = self.until {
+            filter = filter.with_until(until);
+        }
+        for user in &self.users {
+            filter = filter.with_user_id(user);
+        }
+        for namespace in &self.namespaces {
+            filter = filter.with_namespace(namespace);
+        }
+        filter
+    }
+}
+
+pub fn run(args: DecodeArgs) -> Result<()> {
+    let cipher = args.key.cipher()?;
+    let registry = codec_registry(args.zstd_dict.as_deref())?;
+    let mut output = args.output.open()?;
+    let mut format = args.output.record_format();
+    format.write_header(&mut output)?;
+
+    let filter = args.filter.to_filter();
+
+    for path in &args.files {
+        decode_input(
+            path,
+            cipher.as_ref(),
+            &registry,
+            &filter,
+            &mut |meta, content| Ok(format.write_record(&mut output, meta, content)?),
+        )?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// Passes the records of every glog file in `input`, a file, directory,
+/// glob pattern or archive, that pass `filter` to `on_record`. Returns the
+/// number of records in the files.
+pub fn decode_input(
+    input: &Path,
+    cipher: Option<&Cipher>,
+    registry: &CodecRegistry,
+    filter: &EntryFilter,
+    on_record: &mut dyn FnMut(&RecordMeta, &str) -> Result<()>,
+) -> Result<usize> {
+    let mut count = 0;
+    for_each_glog(input, &mut |name, reader| {
+        count += decode_reader(name, reader, cipher, registry, filter, on_record)
+            .with_context(|| format!("failed to decode {}", name))?;
+        Ok::<_, anyhow::Error>(())
+    })
+    .map_err(|error| {
+        if error.is::<InputError>() {
+            error.context(format!("failed to read {}", input.display()))
+        } else {
+            error
+        }
+    })?;
+    Ok(count)
+}
+
+fn decode_reader(
+    file_name: &str,
+    reader: &mut dyn Read,
+    cipher: Option<&Cipher>,
+    registry: &CodecRegistry,
+    filter: &EntryFilter,
+    on_record: &mut dyn FnMut(&RecordMeta, &str) -> Result<()>,
+) -> Result<usize> {
+    let placeholder;
+    let reader_cipher = match cipher {
+        Some(cipher) => cipher,
+        None => {
+            placeholder = placeholder_cipher()?;
+            &placeholder
+        }
+    };
+
+    let mut reader = LogBufReaderV4::new(reader, reader_cipher).with_registry(registry.clone());
+    reader.read_header()?;
+
+    let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+    let mut count = 0;
+
+    while let Some(record) = reader.read_record()? {
+        if record.mode.encrypt != EncryptMode::None && cipher.is_none() {
+            return Err(anyhow::anyhow!(
+                "record at offset {} is encrypted, pass --key",
+                record.offset
+            )
+            .context(Failure::BadKey));
+        }
+
+        buffer.clear();
+        reader.inflate_record(&record, &mut buffer)?;
+
+        let index = count;
+        count += 1;
+
+        let content = String::from_utf8_lossy(&buffer);
+        if !filter.matches_content(&content) {
+            continue;
+        }
+
+        let meta = RecordMeta {
+            file: file_name,
+            index,
+            offset: record.offset,
+        };
+        on_record(&meta, &content)?;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,150 @@
+use crate::key::parse_public_key_source;
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use glog_rust::{
+    cipher::{
+        aes_cfb_ecdh::Cipher,
+        key_pair::{Curve, KeyPair},
+    },
+    io::{
+        codec::CodecRegistry,
+        log_writer::LogBufWriterV4,
+        primitive::{CompressMode, EncryptMode, DEFAULT_PROTO_NAME},
+    },
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter},
+    num::NonZeroU32,
+    path::PathBuf,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Compression {
+    None,
+    Zlib,
+    Zstd,
+    Lz4,
+}
+
+impl From<Compression> for CompressMode {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => CompressMode::None,
+            Compression::Zlib => CompressMode::Zlib,
+            Compression::Zstd => CompressMode::Zstd,
+            Compression::Lz4 => CompressMode::Lz4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Encryption {
+    None,
+    Aes,
+    AesHkdf,
+}
+
+impl From<Encryption> for EncryptMode {
+    fn from(encryption: Encryption) -> Self {
+        match encryption {
+            Encryption::None => EncryptMode::None,
+            Encryption::Aes => EncryptMode::Aes,
+            Encryption::AesHkdf => EncryptMode::AesHkdf,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct EncodeArgs {
+    /// Text file with one record per line, defaults to stdin. Empty lines
+    /// are skipped
+    input: Option<PathBuf>,
+
+    /// Glog file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Server public key: hex, or a public key file in hex, env or PEM format
+    #[arg(long, env = "PUB_KEY")]
+    pub_key: Option<String>,
+
+    /// Curve of the server public key
+    #[arg(long, default_value_t = Curve::Secp256k1)]
+    curve: Curve,
+
+    #[arg(long, value_enum, default_value_t = Compression::Zlib)]
+    compress: Compression,
+
+    /// Defaults to aes when a public key is given, none otherwise
+    #[arg(long, value_enum)]
+    encrypt: Option<Encryption>,
+
+    #[arg(long, default_value = DEFAULT_PROTO_NAME)]
+    proto_name: String,
+
+    /// Start a fresh zlib stream every N records
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    zlib_reset_interval: Option<u32>,
+
+    /// Dictionary for zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+}
+
+pub fn run(args: EncodeArgs) -> Result<()> {
+    let pub_key = args
+        .pub_key
+        .as_deref()
+        .map(parse_public_key_source)
+        .transpose()?;
+    let encrypt = args.encrypt.unwrap_or(match pub_key {
+        Some(_) => Encryption::Aes,
+        None => Encryption::None,
+    });
+    let mode = (args.compress.into(), EncryptMode::from(encrypt));
+
+    if mode.1 != EncryptMode::None && pub_key.is_none() {
+        anyhow::bail!("encrypting records needs --pub-key or PUB_KEY");
+    }
+
+    let client_cipher = Cipher::new_with_curve(
+        &KeyPair::random_with_curve(args.curve)?.private_key,
+        args.curve,
+    )?;
+    let mut registry = CodecRegistry::default();
+    if let Some(path) = &args.zstd_dict {
+        registry = registry.with_zstd(zstd::DEFAULT_COMPRESSION_LEVEL, &std::fs::read(path)?);
+    }
+
+    let output = BufWriter::new(File::create(&args.output)?);
+    let mut writer = LogBufWriterV4::new(output, &client_cipher)
+        .with_registry(registry)
+        .with_proto_name(args.proto_name.as_bytes());
+    if let Some(interval) = args.zlib_reset_interval.and_then(NonZeroU32::new) {
+        writer = writer.with_zlib_reset_interval(interval);
+    }
+    writer.write_head()?;
+
+    let input: Box<dyn BufRead> = match &args.input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let pub_key = pub_key.unwrap_or_default();
+    let mut count = 0;
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        // the format has no empty records
+        if line.is_empty() {
+            continue;
+        }
+        writer
+            .write_single_log(mode, &pub_key, &line)
+            .with_context(|| format!("failed to write line {}", index + 1))?;
+        count += 1;
+    }
+
+    eprintln!("wrote {} records to {}", count, args.output.display());
+    Ok(())
+}
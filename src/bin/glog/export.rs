@@ -0,0 +1,207 @@
+use crate::{
+    decode::{codec_registry, decode_input, FilterArgs},
+    key::KeyArgs,
+};
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+#[cfg(feature = "parquet")]
+use glog_rust::export::parquet;
+use glog_rust::export::RecordSink;
+#[cfg(feature = "export")]
+use glog_rust::export::{elasticsearch, loki, otlp, BatchLimits, RetryPolicy};
+use std::path::PathBuf;
+#[cfg(feature = "parquet")]
+use std::{fs::File, io::BufWriter};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// OpenTelemetry logs over OTLP/HTTP with JSON encoding
+    #[cfg(feature = "export")]
+    Otlp,
+    /// Grafana Loki push API
+    #[cfg(feature = "export")]
+    Loki,
+    /// Elasticsearch bulk API
+    #[cfg(feature = "export")]
+    Elasticsearch,
+    /// Parquet file with one column per standard field
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Glog files, directories, glob patterns or .zip/.tar.gz archives to
+    /// export, in order
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    #[arg(short, long, value_enum)]
+    format: ExportFormat,
+
+    /// File to write, for file formats
+    #[cfg(feature = "parquet")]
+    #[arg(short, long, required_if_eq("format", "parquet"))]
+    output: Option<PathBuf>,
+
+    /// Rows per Parquet row group
+    #[cfg(feature = "parquet")]
+    #[arg(long, default_value_t = parquet::DEFAULT_ROW_GROUP_SIZE)]
+    row_group_size: usize,
+
+    #[cfg(feature = "export")]
+    #[command(flatten)]
+    backend: BackendArgs,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    /// Dictionary used by zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+/// Options of the log backend formats.
+#[cfg(feature = "export")]
+#[derive(Args)]
+struct BackendArgs {
+    /// URL to send records to [default: the format's default port on localhost]
+    #[arg(long)]
+    endpoint: Option<String>,
+
+    /// Extra request header as NAME=VALUE, may be repeated
+    #[arg(long = "header", value_parser = parse_pair)]
+    headers: Vec<(String, String)>,
+
+    /// Most records per request
+    #[arg(long, default_value_t = BatchLimits::default().max_records)]
+    batch_size: usize,
+
+    /// Most encoded bytes per request
+    #[arg(long, default_value_t = BatchLimits::default().max_bytes)]
+    batch_bytes: usize,
+
+    /// Times a request is repeated after connection errors, 429 or 5xx answers
+    #[arg(long, default_value_t = RetryPolicy::default().max_retries)]
+    retries: u32,
+
+    /// service.name resource attribute of OTLP records
+    #[arg(long, default_value = otlp::DEFAULT_SERVICE_NAME)]
+    service_name: String,
+
+    /// Extra label of Loki streams as NAME=VALUE, may be repeated
+    #[arg(long = "label", value_parser = parse_pair)]
+    labels: Vec<(String, String)>,
+
+    /// Elasticsearch index to write to
+    #[arg(long, default_value = elasticsearch::DEFAULT_INDEX)]
+    index: String,
+}
+
+#[cfg(feature = "export")]
+fn parse_pair(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or(format!("expected NAME=VALUE: {}", s))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+#[cfg(feature = "export")]
+impl BackendArgs {
+    fn sink(&self, format: ExportFormat) -> Box<dyn RecordSink> {
+        let limits = BatchLimits {
+            max_records: self.batch_size,
+            max_bytes: self.batch_bytes,
+        };
+        let retry = RetryPolicy {
+            max_retries: self.retries,
+            ..Default::default()
+        };
+
+        match format {
+            ExportFormat::Otlp => {
+                let endpoint = self.endpoint.as_deref().unwrap_or(otlp::DEFAULT_ENDPOINT);
+                let mut exporter = otlp::OtlpExporter::new(endpoint)
+                    .with_service_name(&self.service_name)
+                    .with_batch_limits(limits)
+                    .with_retry(retry);
+                for (name, value) in &self.headers {
+                    exporter = exporter.with_header(name, value);
+                }
+                Box::new(exporter)
+            }
+            ExportFormat::Loki => {
+                let endpoint = self.endpoint.as_deref().unwrap_or(loki::DEFAULT_ENDPOINT);
+                let mut sink = loki::LokiSink::new(endpoint)
+                    .with_batch_limits(limits)
+                    .with_retry(retry);
+                for (name, value) in &self.labels {
+                    sink = sink.with_label(name, value);
+                }
+                for (name, value) in &self.headers {
+                    sink = sink.with_header(name, value);
+                }
+                Box::new(sink)
+            }
+            ExportFormat::Elasticsearch => {
+                let url = self
+                    .endpoint
+                    .as_deref()
+                    .unwrap_or(elasticsearch::DEFAULT_URL);
+                let mut sink = elasticsearch::ElasticsearchSink::new(url)
+                    .with_index(&self.index)
+                    .with_batch_limits(limits)
+                    .with_retry(retry);
+                for (name, value) in &self.headers {
+                    sink = sink.with_header(name, value);
+                }
+                Box::new(sink)
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => unreachable!("parquet is a file format"),
+        }
+    }
+}
+
+impl ExportArgs {
+    fn sink(&self) -> Result<Box<dyn RecordSink>> {
+        match self.format {
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                // required by clap for this format
+                let path = self.output.as_ref().expect("--output is required");
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                let sink = parquet::ParquetSink::new(BufWriter::new(file))?
+                    .with_row_group_size(self.row_group_size);
+                Ok(Box::new(sink))
+            }
+            #[cfg(feature = "export")]
+            format => Ok(self.backend.sink(format)),
+        }
+    }
+}
+
+pub fn run(args: ExportArgs) -> Result<()> {
+    let cipher = args.key.cipher()?;
+    let registry = codec_registry(args.zstd_dict.as_deref())?;
+    let filter = args.filter.to_filter();
+    let mut sink = args.sink()?;
+
+    for path in &args.files {
+        decode_input(
+            path,
+            cipher.as_ref(),
+            &registry,
+            &filter,
+            &mut |meta, content| Ok(sink.write_record(meta, content)?),
+        )
+        .with_context(|| format!("failed to export {}", path.display()))?;
+    }
+
+    sink.finish()?;
+    Ok(())
+}
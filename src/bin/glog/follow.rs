@@ -0,0 +1,95 @@
+use crate::{
+    decode::{codec_registry, FilterArgs, OutputArgs},
+    key::{placeholder_cipher, KeyArgs},
+};
+use anyhow::Result;
+use clap::Args;
+use glog_rust::io::{
+    follow::Follower,
+    metrics::{DecodeFailure, DecodeMetrics},
+};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+#[derive(Args)]
+pub struct FollowArgs {
+    /// Glog file that is still being written; may not exist yet
+    file: PathBuf,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Dictionary used by zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Only print records written after following started
+    #[arg(long)]
+    new_only: bool,
+
+    /// How often to check the file for new data, in milliseconds
+    #[arg(long, default_value_t = 250)]
+    poll_interval: u64,
+}
+
+/// Tells the user about records the follower skips.
+struct WarnOnSkip;
+
+impl DecodeMetrics for WarnOnSkip {
+    fn decode_failed(&self, failure: DecodeFailure) {
+        // other damage is reported by `recovery_skip`, or ends following
+        if failure != DecodeFailure::Other {
+            eprintln!("warning: skipped a record ({})", failure.as_str());
+        }
+    }
+
+    fn recovery_skip(&self, offset: i64) {
+        eprintln!("warning: skipped damaged data at offset {}", offset);
+    }
+}
+
+pub fn run(args: FollowArgs) -> Result<()> {
+    let cipher = match args.key.cipher()? {
+        Some(cipher) => cipher,
+        None => placeholder_cipher()?,
+    };
+
+    let follower = Follower::new(&args.file, &cipher)
+        .with_registry(codec_registry(args.zstd_dict.as_deref())?)
+        .with_poll_interval(Duration::from_millis(args.poll_interval))
+        .with_skip_existing(args.new_only)
+        .with_metrics(Arc::new(WarnOnSkip));
+
+    let filter = args.filter.to_filter();
+    let mut output = args.output.open()?;
+    let mut format = args.output.record_format();
+    format.write_header(&mut output)?;
+    output.flush()?;
+    let mut write_error = None;
+
+    follower.follow(|meta, content| {
+        if !filter.matches_content(content) {
+            return true;
+        }
+        let result = format
+            .write_record(&mut output, meta, content)
+            .and_then(|_| output.flush());
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                write_error = Some(e);
+                false
+            }
+        }
+    })?;
+
+    match write_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,126 @@
+use crate::key::placeholder_cipher;
+use anyhow::{Context, Result};
+use clap::Args;
+use glog_rust::{
+    cipher::key_pair::public_key_fingerprint,
+    io::{
+        log_reader::{LogBufReadError, LogBufReaderV4},
+        primitive::EncryptMode,
+    },
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// Glog files to inspect
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Only print the header and the summary
+    #[arg(short, long)]
+    summary: bool,
+}
+
+pub fn run(args: InspectArgs) -> Result<()> {
+    for path in &args.files {
+        inspect_file(path, args.summary)
+            .with_context(|| format!("failed to inspect {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Summary {
+    records: usize,
+    payload_bytes: usize,
+    largest_payload: usize,
+    modes: BTreeMap<u8, usize>,
+    client_keys: HashSet<String>,
+    broken_sync_markers: usize,
+}
+
+/// Prints the header and record layout of `path` without decrypting it.
+fn inspect_file(path: &Path, summary_only: bool) -> Result<()> {
+    let cipher = placeholder_cipher()?;
+    let mut reader = LogBufReaderV4::new(File::open(path).map(BufReader::new)?, &cipher);
+    reader.read_header()?;
+
+    println!("{}", path.display());
+    println!("  version:    {:?}", reader.version());
+    println!(
+        "  proto name: {}",
+        String::from_utf8_lossy(reader.proto_name())
+    );
+    if let Some(interval) = reader.zlib_reset_interval() {
+        println!("  zlib reset: every {} records", interval);
+    }
+    if let Some(curve) = reader.curve() {
+        println!("  curve:      {}", curve);
+    }
+
+    let mut summary = Summary::default();
+    let result = loop {
+        let record_start = reader.position();
+        let (record, sync_marker_intact) = match reader.read_raw_record_lenient() {
+            Ok(Some(record)) => record,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err((record_start, e)),
+        };
+
+        let encrypted = record.mode.encrypt != EncryptMode::None;
+        let fingerprint = public_key_fingerprint(&record.client_pubkey);
+
+        if !summary_only {
+            println!(
+                "  offset {:>10}  mode {:#04x} ({:?}, {:?})  iv {}  client {}  length {:>5}  sync {}",
+                record.offset,
+                u8::from(record.mode),
+                record.mode.compress,
+                record.mode.encrypt,
+                if encrypted { hex::encode(record.iv) } else { "-".repeat(32) },
+                if encrypted { fingerprint.clone() } else { "-".repeat(16) },
+                record.payload.len(),
+                if sync_marker_intact { "ok" } else { "BROKEN" },
+            );
+        }
+
+        summary.records += 1;
+        summary.payload_bytes += record.payload.len();
+        summary.largest_payload = summary.largest_payload.max(record.payload.len());
+        *summary.modes.entry(record.mode.into()).or_default() += 1;
+        if encrypted {
+            summary.client_keys.insert(fingerprint);
+        }
+        if !sync_marker_intact {
+            summary.broken_sync_markers += 1;
+        }
+    };
+
+    println!(
+        "  records:      {} ({} payload bytes, largest {})",
+        summary.records, summary.payload_bytes, summary.largest_payload
+    );
+    for (mode, count) in &summary.modes {
+        println!("  mode {:#04x}:    {}", mode, count);
+    }
+    println!("  client keys:  {}", summary.client_keys.len());
+    println!(
+        "  sync markers: {} intact, {} broken",
+        summary.records - summary.broken_sync_markers,
+        summary.broken_sync_markers
+    );
+
+    if let Err((record_start, e)) = result {
+        println!("  stopped at offset {}", record_start);
+        return Err(e.into());
+    }
+    if summary.broken_sync_markers > 0 {
+        return Err(LogBufReadError::InvalidSyncMarker.into());
+    }
+    Ok(())
+}
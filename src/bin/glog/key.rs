@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::Args;
+use glog_rust::cipher::{
+    aes_cfb_ecdh::Cipher,
+    key_file::{parse_private_key, parse_public_key},
+    key_pair::{Curve, KeyPair},
+};
+use std::path::Path;
+
+#[derive(Args)]
+pub struct KeyArgs {
+    /// Server private key: hex, or a key file in hex, env or PEM format
+    #[arg(short, long, env = "PRI_KEY", hide_env_values = true)]
+    key: Option<String>,
+
+    /// Curve of the server key, ignored for PEM keys
+    #[arg(long, default_value_t = Curve::Secp256k1)]
+    curve: Curve,
+}
+
+impl KeyArgs {
+    pub fn curve(&self) -> Curve {
+        self.curve
+    }
+
+    /// Cipher for the given key, or `None` when no key was given.
+    pub fn cipher(&self) -> Result<Option<Cipher>> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        let key_pair = parse_private_key(&read_key_source(key)?, self.curve)?;
+        Ok(Some(Cipher::new_with_curve(
+            &key_pair.private_key,
+            key_pair.curve(),
+        )?))
+    }
+
+    /// Like [`cipher`](Self::cipher), but fails when no key was given.
+    pub fn require_cipher(&self) -> Result<Cipher> {
+        self.cipher()?
+            .ok_or(anyhow::anyhow!("no key given, pass --key or set PRI_KEY"))
+    }
+}
+
+/// Parses a public key given as hex or as the path of a public key file.
+pub fn parse_public_key_source(source: &str) -> Result<String> {
+    Ok(parse_public_key(&read_key_source(source)?)?)
+}
+
+/// Cipher with a throwaway key, for readers that never decrypt.
+pub fn placeholder_cipher() -> Result<Cipher> {
+    Ok(Cipher::new(&KeyPair::random()?.private_key)?)
+}
+
+fn read_key_source(source: &str) -> Result<String> {
+    let path = Path::new(source);
+    if path.is_file() {
+        return Ok(std::fs::read_to_string(path)?);
+    }
+    Ok(source.to_string())
+}
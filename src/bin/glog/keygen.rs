@@ -0,0 +1,61 @@
+use anyhow::Result;
+use clap::Args;
+use glog_rust::cipher::{
+    key_file::{write_key_files, KeyFileError, KeyFormat},
+    key_pair::{Curve, KeyPair},
+};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct KeygenArgs {
+    /// Private key file; the public key is written next to it with a .pub suffix
+    #[arg(short, long, default_value = "glog.key")]
+    output: PathBuf,
+
+    /// Public key file, defaults to <OUTPUT>.pub
+    #[arg(long)]
+    public_key: Option<PathBuf>,
+
+    /// Key file format: hex, env or pem
+    #[arg(short, long, default_value_t = KeyFormat::Hex)]
+    format: KeyFormat,
+
+    #[arg(long, default_value_t = Curve::Secp256k1)]
+    curve: Curve,
+
+    /// Overwrite existing key files
+    #[arg(long)]
+    force: bool,
+}
+
+pub fn run(args: KeygenArgs) -> Result<()> {
+    let public_key_path = args.public_key.unwrap_or_else(|| {
+        let mut path = args.output.clone().into_os_string();
+        path.push(".pub");
+        path.into()
+    });
+
+    let key_pair = KeyPair::random_with_curve(args.curve)?;
+    write_key_files(
+        &key_pair,
+        &args.output,
+        &public_key_path,
+        args.format,
+        args.force,
+    )
+    .map_err(|e| match e {
+        KeyFileError::AlreadyExists(path) => {
+            anyhow::anyhow!(
+                "{} already exists, pass --force to overwrite",
+                path.display()
+            )
+        }
+        e => e.into(),
+    })?;
+
+    println!("private key: {}", args.output.display());
+    println!("public key:  {}", public_key_path.display());
+    println!("curve:       {}", key_pair.curve());
+    println!("fingerprint: {}", key_pair.fingerprint()?);
+    Ok(())
+}
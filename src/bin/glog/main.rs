@@ -0,0 +1,214 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use glog_rust::{
+    cipher::{error::CipherError, key_file::KeyFileError},
+    io::{input::InputError, log_reader::LogBufReadError},
+};
+use std::process::ExitCode;
+use thiserror::Error;
+
+mod decode;
+mod encode;
+#[cfg(any(feature = "export", feature = "parquet"))]
+mod export;
+mod follow;
+mod inspect;
+mod key;
+mod keygen;
+mod merge;
+mod reencrypt;
+
+#[derive(Parser)]
+#[command(name = "glog", version, about = "Tools for hll-glog files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode glog files to text, one record per line
+    Decode(decode::DecodeArgs),
+
+    /// Write lines of text as a glog file
+    Encode(encode::EncodeArgs),
+
+    /// Generate a server key pair
+    Keygen(keygen::KeygenArgs),
+
+    /// Print records of a file as it is being written, like tail -f
+    Follow(follow::FollowArgs),
+
+    /// Merge glog files into one stream ordered by record timestamp
+    Merge(merge::MergeArgs),
+
+    /// Show the structure of glog files without decrypting them
+    Inspect(inspect::InspectArgs),
+
+    /// Re-encrypt a glog file for a new server key
+    Reencrypt(reencrypt::ReencryptArgs),
+
+    /// Send decoded records to a log backend or a columnar file
+    #[cfg(any(feature = "export", feature = "parquet"))]
+    Export(export::ExportArgs),
+}
+
+/// Failures attached as error context where the underlying error alone does
+/// not tell which exit code applies.
+#[derive(Debug, Error)]
+pub enum Failure {
+    #[error("wrong or missing key")]
+    BadKey,
+}
+
+/// Exit codes besides 0 (success), 1 (other errors) and 2 (usage errors).
+const EXIT_BAD_KEY: u8 = 3;
+const EXIT_CORRUPT_INPUT: u8 = 4;
+
+fn exit_code(error: &anyhow::Error) -> u8 {
+    if let Some(Failure::BadKey) = error.downcast_ref::<Failure>() {
+        return EXIT_BAD_KEY;
+    }
+
+    for cause in error.chain() {
+        // failing to read or write a key file is not a bad key
+        if cause.is::<CipherError>() {
+            return EXIT_BAD_KEY;
+        }
+        if let Some(KeyFileError::CipherError(_)) = cause.downcast_ref::<KeyFileError>() {
+            return EXIT_BAD_KEY;
+        }
+        if let Some(InputError::Zip(_) | InputError::Tar(_)) = cause.downcast_ref::<InputError>() {
+            return EXIT_CORRUPT_INPUT;
+        }
+        if let Some(e) = cause.downcast_ref::<LogBufReadError>() {
+            return match e {
+                LogBufReadError::DecryptionError(_)
+                | LogBufReadError::InvalidSecret
+                | LogBufReadError::WrongKey { .. } => EXIT_BAD_KEY,
+                _ => EXIT_CORRUPT_INPUT,
+            };
+        }
+    }
+
+    1
+}
+
+fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Decode(args) => decode::run(args),
+        Command::Encode(args) => encode::run(args),
+        Command::Keygen(args) => keygen::run(args),
+        Command::Follow(args) => follow::run(args),
+        Command::Merge(args) => merge::run(args),
+        Command::Inspect(args) => inspect::run(args),
+        Command::Reencrypt(args) => reencrypt::run(args),
+        #[cfg(any(feature = "export", feature = "parquet"))]
+        Command::Export(args) => export::run(args),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {:#}", error);
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exit_code, run, Cli, EXIT_BAD_KEY, EXIT_CORRUPT_INPUT};
+    use anyhow::Result;
+    use clap::Parser;
+    use glog_rust::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use std::{fs, io::BufWriter};
+
+    /// Exit code of `glog <args>`.
+    fn glog(args: &[&str]) -> u8 {
+        let cli = Cli::try_parse_from(std::iter::once("glog").chain(args.iter().copied()))
+            .expect("valid arguments");
+        match run(cli.command) {
+            Ok(()) => 0,
+            Err(error) => exit_code(&error),
+        }
+    }
+
+    #[test]
+    fn test_exit_codes() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let server_key = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let mut writer = LogBufWriterV4::new(
+            BufWriter::new(fs::File::create(path("app.glog"))?),
+            &client_cipher,
+        );
+        writer.write_head()?;
+        for log in [r#"{"msg":"a"}"#, r#"{"msg":"b"}"#] {
+            writer.write_single_log(
+                (CompressMode::Zlib, EncryptMode::Aes),
+                &server_key.public_key,
+                log,
+            )?;
+        }
+        drop(writer);
+
+        let (file, output) = (path("app.glog"), path("out"));
+        let right_key = server_key.private_key.as_str();
+        assert_eq!(
+            glog(&["decode", &file, "--key", right_key, "-o", &output]),
+            0
+        );
+
+        // a key on the right curve decrypts to garbage that fails to inflate
+        let wrong_key = KeyPair::random()?.private_key;
+        #[cfg_attr(not(feature = "parquet"), allow(unused_mut))]
+        let mut commands = vec![
+            vec!["decode", &file, "--key", &wrong_key, "-o", &output],
+            vec!["merge", &file, "--key", &wrong_key, "-o", &output],
+            vec!["follow", &file, "--key", &wrong_key, "-o", &output],
+            vec![
+                "reencrypt",
+                &file,
+                "-o",
+                &output,
+                "--key",
+                &wrong_key,
+                "--new-pub-key",
+                &server_key.public_key,
+            ],
+        ];
+        #[cfg(feature = "parquet")]
+        commands.push(vec![
+            "export", &file, "--format", "parquet", "-o", &output, "--key", &wrong_key,
+        ]);
+        for command in &commands {
+            assert_eq!(glog(command), EXIT_BAD_KEY, "{:?}", command);
+        }
+
+        fs::write(path("broken.zip"), "not a zip archive")?;
+        fs::write(path("broken.tar.gz"), "not gzip data")?;
+        for archive in [path("broken.zip"), path("broken.tar.gz")] {
+            assert_eq!(
+                glog(&["decode", &archive, "--key", right_key, "-o", &output]),
+                EXIT_CORRUPT_INPUT,
+                "{}",
+                archive
+            );
+        }
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{
+    decode::{codec_registry, FilterArgs, OutputArgs},
+    key::{placeholder_cipher, KeyArgs},
+};
+use anyhow::Result;
+use clap::Args;
+use glog_rust::{format::RecordMeta, io::merge::MergeReader};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct MergeArgs {
+    /// Glog files to merge, each in time order
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Dictionary used by zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+pub fn run(args: MergeArgs) -> Result<()> {
+    let cipher = match args.key.cipher()? {
+        Some(cipher) => cipher,
+        None => placeholder_cipher()?,
+    };
+
+    let mut merge =
+        MergeReader::new(&cipher).with_registry(codec_registry(args.zstd_dict.as_deref())?);
+    for path in &args.files {
+        merge.add_file(path)?;
+    }
+
+    let filter = args.filter.to_filter();
+    let mut output = args.output.open()?;
+    let mut format = args.output.record_format();
+    format.write_header(&mut output)?;
+
+    for record in merge.records() {
+        let record = record?;
+        if !filter.matches_content(&record.content) {
+            continue;
+        }
+
+        let meta = RecordMeta {
+            file: &record.source,
+            index: record.index,
+            offset: record.offset,
+        };
+        format.write_record(&mut output, &meta, &record.content)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use crate::key::{parse_public_key_source, KeyArgs};
+use anyhow::{Context, Result};
+use clap::Args;
+use glog_rust::{
+    cipher::key_pair::Curve,
+    io::reencrypt::{reencrypt, ReencryptOptions},
+};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+#[derive(Args)]
+pub struct ReencryptArgs {
+    /// Glog file to read
+    input: PathBuf,
+
+    /// Where to write the re-encrypted file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    /// New server public key: hex, or a public key file
+    #[arg(long, env = "NEW_PUB_KEY")]
+    new_pub_key: String,
+
+    /// Curve of the new server key, defaults to --curve
+    #[arg(long)]
+    new_curve: Option<Curve>,
+
+    /// Copy compressed payloads without inflating them
+    #[arg(long)]
+    passthrough: bool,
+
+    /// Dictionary used by zstd records
+    #[arg(long)]
+    zstd_dict: Option<PathBuf>,
+}
+
+/// Writes `path` through a temporary file next to it, so that it only
+/// appears once complete and a failure leaves no partial file behind.
+fn write_atomically<T>(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T>,
+) -> Result<T> {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
+
+    let mut output = BufWriter::new(File::create(&temp)?);
+    let result = write(&mut output).and_then(|value| {
+        output.into_inner()?.sync_all()?;
+        std::fs::rename(&temp, path)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+pub fn run(args: ReencryptArgs) -> Result<()> {
+    let input = File::open(&args.input)
+        .with_context(|| format!("failed to open {}", args.input.display()))?;
+    // relative paths, `..` and symlinks may name the input too
+    if let Ok(output) = args.output.canonicalize() {
+        if output == args.input.canonicalize()? {
+            anyhow::bail!("input and output must be different files");
+        }
+    }
+
+    let cipher = args.key.require_cipher()?;
+    let new_pub_key = parse_public_key_source(&args.new_pub_key)?;
+    let options = ReencryptOptions {
+        curve: args.new_curve.unwrap_or(args.key.curve()),
+        passthrough_compressed: args.passthrough,
+        zstd_dictionary: args.zstd_dict.map(std::fs::read).transpose()?,
+    };
+
+    let count = write_atomically(&args.output, |output| {
+        reencrypt(
+            BufReader::new(input),
+            output,
+            &cipher,
+            &new_pub_key,
+            &options,
+        )
+    })?;
+
+    eprintln!(
+        "re-encrypted {} records from {} to {}",
+        count,
+        args.input.display(),
+        args.output.display()
+    );
+    Ok(())
+}
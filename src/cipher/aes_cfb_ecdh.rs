@@ -1,17 +1,43 @@
-use super::key_pair::KeyPair;
+use super::{
+    error::CipherError,
+    key_pair::{Curve, KeyPair, SharedSecret},
+};
 use aes::{
     cipher::{AsyncStreamCipher, KeyIvInit},
     Aes128,
 };
-use anyhow::Result;
 use cfb_mode::{Decryptor, Encryptor};
-use elliptic_curve::ecdh::SharedSecret;
-use k256::Secp256k1;
+use hkdf::Hkdf;
 use rand::{thread_rng, Rng};
+use sha2::Sha256;
 
 type Aes128CfbDec = Decryptor<Aes128>;
 type Aes128CfbEnc = Encryptor<Aes128>;
 
+pub const IV_LENGTH: usize = 16;
+
+/// How the AES-128 key is derived from the ECDH shared secret.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum KeyDerivation<'a> {
+    /// Legacy scheme: the first 16 bytes of the raw shared secret.
+    #[default]
+    Truncate,
+
+    /// HKDF-SHA256 over the raw shared secret. The reader and writer use the
+    /// record IV as salt and the file's proto name as info.
+    HkdfSha256 { salt: &'a [u8], info: &'a [u8] },
+}
+
+fn check_iv(iv: &[u8]) -> Result<(), CipherError> {
+    if iv.len() != IV_LENGTH {
+        return Err(CipherError::WrongIvLength {
+            expected: IV_LENGTH,
+            actual: iv.len(),
+        });
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Cipher {
     key_pair: KeyPair,
@@ -21,48 +47,98 @@ unsafe impl Send for Cipher {}
 unsafe impl Sync for Cipher {}
 
 impl Cipher {
-    pub fn new(pri_key_str: &str) -> Result<Self> {
-        let key_pair = KeyPair::from_private_key_str(pri_key_str)?;
+    pub fn new(pri_key_str: &str) -> Result<Self, CipherError> {
+        Self::new_with_curve(pri_key_str, Curve::default())
+    }
+
+    pub fn new_with_curve(pri_key_str: &str, curve: Curve) -> Result<Self, CipherError> {
+        let key_pair = KeyPair::from_private_key_str_with_curve(pri_key_str, curve)?;
         Ok(Self { key_pair })
     }
 
+    pub fn curve(&self) -> Curve {
+        self.key_pair.curve()
+    }
+
     pub fn get_key_pair(&self) -> &KeyPair {
         &self.key_pair
     }
 
-    pub fn get_shared_key(&self, swaped_pub_key: &[u8]) -> Result<SharedSecret<Secp256k1>> {
+    pub fn get_shared_key(&self, swaped_pub_key: &[u8]) -> Result<SharedSecret, CipherError> {
         self.key_pair.diffie_hellman(swaped_pub_key)
     }
 
-    pub fn random_iv() -> [u8; 16] {
-        let mut iv = [0u8; 16];
+    pub fn random_iv() -> [u8; IV_LENGTH] {
+        let mut iv = [0u8; IV_LENGTH];
         thread_rng().fill(&mut iv[..]);
         iv
     }
 
+    pub fn derive_aes_key(
+        &self,
+        swaped_pub_key: &[u8],
+        kdf: &KeyDerivation,
+    ) -> Result<[u8; 16], CipherError> {
+        let shared = self.get_shared_key(swaped_pub_key)?;
+        let mut aes_key = [0u8; 16];
+
+        match kdf {
+            KeyDerivation::Truncate => {
+                aes_key.copy_from_slice(&shared.raw_secret_bytes()[0..16]);
+            }
+            KeyDerivation::HkdfSha256 { salt, info } => {
+                Hkdf::<Sha256>::new(Some(salt), shared.raw_secret_bytes())
+                    .expand(info, &mut aes_key)
+                    .map_err(|_| CipherError::KeyDerivation)?;
+            }
+        }
+
+        Ok(aes_key)
+    }
+
     pub fn decrypt_inplace(
         &self,
         swaped_pub_key: &[u8],
         iv: &[u8],
         buffer: &mut [u8],
-    ) -> Result<()> {
-        let shared = self.get_shared_key(swaped_pub_key)?;
-        let aes_key = &shared.raw_secret_bytes()[0..16];
-        let cipher = Aes128CfbDec::new(aes_key.into(), iv.into());
+    ) -> Result<(), CipherError> {
+        self.decrypt_inplace_with(&KeyDerivation::Truncate, swaped_pub_key, iv, buffer)
+    }
+
+    pub fn encrypt_inplace(
+        &self,
+        swaped_pub_key: &[u8],
+        iv: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), CipherError> {
+        self.encrypt_inplace_with(&KeyDerivation::Truncate, swaped_pub_key, iv, buffer)
+    }
+
+    pub fn decrypt_inplace_with(
+        &self,
+        kdf: &KeyDerivation,
+        swaped_pub_key: &[u8],
+        iv: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), CipherError> {
+        check_iv(iv)?;
+        let aes_key = self.derive_aes_key(swaped_pub_key, kdf)?;
+        let cipher = Aes128CfbDec::new(&aes_key.into(), iv.into());
 
         cipher.decrypt(buffer);
         Ok(())
     }
 
-    pub fn encrypt_inplace(
+    pub fn encrypt_inplace_with(
         &self,
+        kdf: &KeyDerivation,
         swaped_pub_key: &[u8],
         iv: &[u8],
         buffer: &mut [u8],
-    ) -> Result<()> {
-        let shared = self.get_shared_key(swaped_pub_key)?;
-        let aes_key = &shared.raw_secret_bytes()[0..16];
-        let cipher = Aes128CfbEnc::new(aes_key.into(), iv.into());
+    ) -> Result<(), CipherError> {
+        check_iv(iv)?;
+        let aes_key = self.derive_aes_key(swaped_pub_key, kdf)?;
+        let cipher = Aes128CfbEnc::new(&aes_key.into(), iv.into());
 
         cipher.encrypt(buffer);
         Ok(())
@@ -72,8 +148,12 @@ impl Cipher {
 #[cfg(test)]
 mod tests {
     use super::Cipher;
+    use super::CipherError;
+    use super::Curve;
+    use super::KeyDerivation;
     use super::KeyPair;
     use anyhow::Result;
+    use elliptic_curve::sec1::ToEncodedPoint;
 
     #[test]
     fn test_encryption() -> Result<()> {
@@ -107,4 +187,125 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_hkdf_encryption() -> Result<()> {
+        let plain_text = b"hello world";
+        let client_key_pair = KeyPair::random()?;
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&client_key_pair.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+        let server_pub_key = server_key_pair.to_public_key_untagged_bytes()?;
+        let random_iv = Cipher::random_iv();
+
+        let kdf = KeyDerivation::HkdfSha256 {
+            salt: &random_iv,
+            info: b"ATRealTimeLog",
+        };
+
+        let legacy_key = client_cipher.derive_aes_key(&server_pub_key, &KeyDerivation::Truncate)?;
+        let hkdf_key = client_cipher.derive_aes_key(&server_pub_key, &kdf)?;
+        assert_ne!(legacy_key, hkdf_key);
+
+        let mut buffer = plain_text.to_vec();
+        client_cipher.encrypt_inplace_with(&kdf, &server_pub_key, &random_iv, &mut buffer)?;
+        server_cipher.decrypt_inplace_with(
+            &kdf,
+            &client_key_pair.to_public_key_untagged_bytes()?,
+            &random_iv,
+            &mut buffer,
+        )?;
+
+        assert!(buffer == plain_text);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_p256_encryption() -> Result<()> {
+        let plain_text = b"hello world";
+        let client_key_pair = KeyPair::random_with_curve(Curve::P256)?;
+        let server_key_pair = KeyPair::random_with_curve(Curve::P256)?;
+        let client_cipher = Cipher::new_with_curve(&client_key_pair.private_key, Curve::P256)?;
+        let server_cipher = Cipher::new_with_curve(&server_key_pair.private_key, Curve::P256)?;
+        let random_iv = Cipher::random_iv();
+        let mut buffer = plain_text.to_vec();
+
+        client_cipher.encrypt_inplace(
+            &server_key_pair.to_public_key_untagged_bytes()?,
+            &random_iv,
+            &mut buffer,
+        )?;
+        server_cipher.decrypt_inplace(
+            &client_key_pair.to_public_key_untagged_bytes()?,
+            &random_iv,
+            &mut buffer,
+        )?;
+
+        assert!(buffer == plain_text);
+
+        // a secp256k1 key cannot be used against a P-256 peer
+        let k256_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        assert!(k256_cipher
+            .encrypt_inplace(
+                &server_key_pair.to_public_key_untagged_bytes()?,
+                &random_iv,
+                &mut buffer,
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cipher_errors() -> Result<()> {
+        assert!(matches!(
+            Cipher::new("not hex"),
+            Err(CipherError::InvalidHex(_))
+        ));
+        assert!(matches!(
+            Cipher::new(&"00".repeat(32)),
+            Err(CipherError::InvalidPrivateKey)
+        ));
+
+        let cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let iv = Cipher::random_iv();
+        let mut buffer = b"hello world".to_vec();
+
+        assert!(matches!(
+            cipher.decrypt_inplace(&[0u8; 32], &iv, &mut buffer),
+            Err(CipherError::WrongKeyLength {
+                expected: 64,
+                actual: 32
+            })
+        ));
+        assert!(matches!(
+            cipher.decrypt_inplace(&[1u8; 64], &iv, &mut buffer),
+            Err(CipherError::PointNotOnCurve)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_key_conversions() -> Result<()> {
+        let key_pair = KeyPair::random()?;
+        let public_key: Result<k256::PublicKey> = (&key_pair).into();
+        let untagged = public_key?.to_encoded_point(false).as_bytes()[1..].to_vec();
+        assert_eq!(untagged, key_pair.to_public_key_untagged_bytes()?);
+
+        // derived from the private key, not the public key field
+        let mut edited = key_pair.clone();
+        edited.public_key = KeyPair::random()?.public_key;
+        assert_eq!(untagged, edited.to_public_key_untagged_bytes()?);
+
+        let p256_key_pair = KeyPair::random_with_curve(Curve::P256)?;
+        let secret_key: Result<k256::SecretKey> = (&p256_key_pair).into();
+        assert!(matches!(
+            secret_key.unwrap_err().downcast_ref(),
+            Some(CipherError::CurveMismatch { .. })
+        ));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,32 @@
+use super::key_pair::Curve;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CipherError {
+    #[error("invalid hex string")]
+    InvalidHex(#[from] hex::FromHexError),
+
+    #[error("invalid private key")]
+    InvalidPrivateKey,
+
+    #[error("invalid public key encoding")]
+    InvalidPublicKey,
+
+    #[error("public key point is not on the curve")]
+    PointNotOnCurve,
+
+    #[error("wrong key length: expected {expected} bytes, got {actual}")]
+    WrongKeyLength { expected: usize, actual: usize },
+
+    #[error("wrong iv length: expected {expected} bytes, got {actual}")]
+    WrongIvLength { expected: usize, actual: usize },
+
+    #[error("key derivation failed")]
+    KeyDerivation,
+
+    #[error("invalid pem key")]
+    InvalidPem,
+
+    #[error("records are encrypted for a {expected} key, not {actual}")]
+    CurveMismatch { expected: Curve, actual: Curve },
+}
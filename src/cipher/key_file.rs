@@ -0,0 +1,278 @@
+use super::{
+    error::CipherError,
+    key_pair::{Curve, KeyPair},
+};
+use elliptic_curve::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+    sec1::ToEncodedPoint,
+};
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum KeyFileError {
+    #[error("{0} already exists")]
+    AlreadyExists(PathBuf),
+
+    #[error("io error")]
+    IoError(#[from] io::Error),
+
+    #[error("key encoding error")]
+    CipherError(#[from] CipherError),
+}
+
+/// On-disk encoding of a key file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyFormat {
+    /// Upper case hex, as used by `Cipher::new` and the writer.
+    #[default]
+    Hex,
+
+    /// A dotenv line, `PRI_KEY="..."` or `PUB_KEY="..."`.
+    Env,
+
+    /// PKCS#8 private key or SPKI public key, PEM encoded.
+    Pem,
+}
+
+impl FromStr for KeyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => Ok(KeyFormat::Hex),
+            "env" => Ok(KeyFormat::Env),
+            "pem" => Ok(KeyFormat::Pem),
+            _ => Err(format!("unknown key format: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyFormat::Hex => write!(f, "hex"),
+            KeyFormat::Env => write!(f, "env"),
+            KeyFormat::Pem => write!(f, "pem"),
+        }
+    }
+}
+
+pub fn encode_private_key(key_pair: &KeyPair, format: KeyFormat) -> Result<String, CipherError> {
+    match format {
+        KeyFormat::Hex => Ok(format!("{}\n", key_pair.private_key)),
+        KeyFormat::Env => Ok(format!("PRI_KEY=\"{}\"\n", key_pair.private_key)),
+        KeyFormat::Pem => {
+            let bytes = hex::decode(&key_pair.private_key)?;
+            let pem = match key_pair.curve() {
+                Curve::Secp256k1 => k256::SecretKey::from_slice(&bytes)
+                    .map_err(|_| CipherError::InvalidPrivateKey)?
+                    .to_pkcs8_pem(LineEnding::LF),
+                Curve::P256 => p256::SecretKey::from_slice(&bytes)
+                    .map_err(|_| CipherError::InvalidPrivateKey)?
+                    .to_pkcs8_pem(LineEnding::LF),
+            };
+            Ok(pem.map_err(|_| CipherError::InvalidPem)?.to_string())
+        }
+    }
+}
+
+pub fn encode_public_key(key_pair: &KeyPair, format: KeyFormat) -> Result<String, CipherError> {
+    match format {
+        KeyFormat::Hex => Ok(format!("{}\n", key_pair.public_key)),
+        KeyFormat::Env => Ok(format!("PUB_KEY=\"{}\"\n", key_pair.public_key)),
+        KeyFormat::Pem => {
+            let mut tagged = vec![0x04];
+            tagged.extend_from_slice(&key_pair.to_public_key_untagged_bytes()?);
+            let pem = match key_pair.curve() {
+                Curve::Secp256k1 => k256::PublicKey::from_sec1_bytes(&tagged)
+                    .map_err(|_| CipherError::InvalidPublicKey)?
+                    .to_public_key_pem(LineEnding::LF),
+                Curve::P256 => p256::PublicKey::from_sec1_bytes(&tagged)
+                    .map_err(|_| CipherError::InvalidPublicKey)?
+                    .to_public_key_pem(LineEnding::LF),
+            };
+            pem.map_err(|_| CipherError::InvalidPem)
+        }
+    }
+}
+
+/// Finds the hex key in a hex or dotenv key file.
+fn parse_key(contents: &str, env_name: &str) -> Option<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .find_map(|line| match line.split_once('=') {
+            Some((name, value)) if name.trim() == env_name => {
+                Some(value.trim().trim_matches('"').to_string())
+            }
+            Some(_) => None,
+            None => Some(line.to_string()),
+        })
+}
+
+/// Parses a private key written by [`encode_private_key`] in any format. PEM
+/// keys carry their curve, which then takes precedence over `curve`.
+pub fn parse_private_key(contents: &str, curve: Curve) -> Result<KeyPair, CipherError> {
+    if contents.contains("-----BEGIN") {
+        if let Ok(secret) = k256::SecretKey::from_pkcs8_pem(contents) {
+            return KeyPair::from_secret_key(&secret);
+        }
+        let secret =
+            p256::SecretKey::from_pkcs8_pem(contents).map_err(|_| CipherError::InvalidPem)?;
+        return KeyPair::from_secret_key(&secret);
+    }
+
+    let hex_key = parse_key(contents, "PRI_KEY").ok_or(CipherError::InvalidPrivateKey)?;
+    KeyPair::from_private_key_str_with_curve(&hex_key, curve)
+}
+
+/// Parses a public key written by [`encode_public_key`] in any format and
+/// returns it as the hex string expected by the writer.
+pub fn parse_public_key(contents: &str) -> Result<String, CipherError> {
+    if contents.contains("-----BEGIN") {
+        let point = match k256::PublicKey::from_public_key_pem(contents) {
+            Ok(pub_key) => pub_key.to_encoded_point(false).as_bytes().to_vec(),
+            Err(_) => p256::PublicKey::from_public_key_pem(contents)
+                .map_err(|_| CipherError::InvalidPem)?
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+        };
+        return Ok(hex::encode_upper(&point[1..]));
+    }
+
+    let hex_key = parse_key(contents, "PUB_KEY").ok_or(CipherError::InvalidPublicKey)?;
+    hex::decode(&hex_key)?;
+    Ok(hex_key.to_ascii_uppercase())
+}
+
+/// Writes `contents` to a file readable only by its owner (mode 0600 on
+/// unix). Existing files are only replaced when `force` is set.
+pub fn write_private_file(path: &Path, contents: &str, force: bool) -> Result<(), KeyFileError> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+
+        // mode() only applies to newly created files
+        if force && path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => KeyFileError::AlreadyExists(path.to_path_buf()),
+        _ => e.into(),
+    })?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Writes the private and public half of `key_pair` to separate files.
+///
+/// Nothing is written if either file exists and `force` is not set. The
+/// private key file is created with mode 0600 on unix.
+pub fn write_key_files(
+    key_pair: &KeyPair,
+    private_key_path: &Path,
+    public_key_path: &Path,
+    format: KeyFormat,
+    force: bool,
+) -> Result<(), KeyFileError> {
+    if !force {
+        for path in [private_key_path, public_key_path] {
+            if path.exists() {
+                return Err(KeyFileError::AlreadyExists(path.to_path_buf()));
+            }
+        }
+    }
+
+    let private_key = encode_private_key(key_pair, format)?;
+    let public_key = encode_public_key(key_pair, format)?;
+
+    write_private_file(private_key_path, &private_key, force)?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    options
+        .open(public_key_path)?
+        .write_all(public_key.as_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_private_key, parse_public_key, write_key_files, KeyFileError, KeyFormat};
+    use crate::cipher::key_pair::{Curve, KeyPair};
+    use anyhow::Result;
+    use std::fs;
+
+    #[test]
+    fn test_write_key_files() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-key-file-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        for curve in [Curve::Secp256k1, Curve::P256] {
+            for format in [KeyFormat::Hex, KeyFormat::Env, KeyFormat::Pem] {
+                let key_pair = KeyPair::random_with_curve(curve)?;
+                let private_key_path = dir.join(format!("{}-{}.key", curve, format));
+                let public_key_path = dir.join(format!("{}-{}.pub", curve, format));
+
+                write_key_files(
+                    &key_pair,
+                    &private_key_path,
+                    &public_key_path,
+                    format,
+                    false,
+                )?;
+                assert!(matches!(
+                    write_key_files(
+                        &key_pair,
+                        &private_key_path,
+                        &public_key_path,
+                        format,
+                        false
+                    ),
+                    Err(KeyFileError::AlreadyExists(_))
+                ));
+                write_key_files(&key_pair, &private_key_path, &public_key_path, format, true)?;
+
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mode = fs::metadata(&private_key_path)?.permissions().mode();
+                    assert_eq!(mode & 0o777, 0o600);
+                }
+
+                let parsed = parse_private_key(&fs::read_to_string(&private_key_path)?, curve)?;
+                assert_eq!(parsed.private_key, key_pair.private_key);
+                assert_eq!(parsed.curve(), curve);
+
+                let public_key = parse_public_key(&fs::read_to_string(&public_key_path)?)?;
+                assert_eq!(public_key, key_pair.public_key);
+            }
+        }
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,53 +1,212 @@
-use anyhow::{Error, Result};
+use super::error::CipherError;
 use elliptic_curve::{
-    sec1::{FromEncodedPoint, ToEncodedPoint},
-    ScalarPrimitive,
-};
-use k256::{
-    ecdh::{diffie_hellman, SharedSecret},
-    EncodedPoint, PublicKey, SecretKey,
+    ecdh,
+    sec1::{EncodedPoint, FromEncodedPoint, ModulusSize, ToEncodedPoint},
+    AffinePoint, CurveArithmetic, FieldBytesSize, PublicKey, ScalarPrimitive, SecretKey,
 };
+use k256::Secp256k1;
+use p256::NistP256;
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+
+/// Length of an untagged (`x || y`) public key on the supported curves.
+pub const UNTAGGED_PUBLIC_KEY_LENGTH: usize = 64;
+
+/// Elliptic curve used for the ECDH key exchange.
+///
+/// Both curves encode public keys as 64 untagged bytes (`x || y`), so the
+/// record format is the same; the curve is a property of the key pair and
+/// must match on both ends. Writers with P-256 keys record the curve in a V5
+/// header, while secp256k1 files stay V4 as before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Curve {
+    #[default]
+    Secp256k1,
+    P256,
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "secp256k1" | "k256" => Ok(Curve::Secp256k1),
+            "p256" | "p-256" | "secp256r1" | "prime256v1" => Ok(Curve::P256),
+            _ => Err(format!("unknown curve: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Curve::Secp256k1 => write!(f, "secp256k1"),
+            Curve::P256 => write!(f, "p256"),
+        }
+    }
+}
+
+impl From<Curve> for u8 {
+    fn from(curve: Curve) -> u8 {
+        match curve {
+            Curve::Secp256k1 => 1,
+            Curve::P256 => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for Curve {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Curve::Secp256k1),
+            2 => Ok(Curve::P256),
+            other => Err(other),
+        }
+    }
+}
+
+/// Curves that can back a [`KeyPair`].
+pub trait EcdhCurve: CurveArithmetic {
+    const CURVE: Curve;
+}
+
+impl EcdhCurve for Secp256k1 {
+    const CURVE: Curve = Curve::Secp256k1;
+}
+
+impl EcdhCurve for NistP256 {
+    const CURVE: Curve = Curve::P256;
+}
+
+/// ECDH shared secret on any supported curve.
+pub enum SharedSecret {
+    Secp256k1(ecdh::SharedSecret<Secp256k1>),
+    P256(ecdh::SharedSecret<NistP256>),
+}
+
+impl SharedSecret {
+    pub fn raw_secret_bytes(&self) -> &[u8] {
+        match self {
+            SharedSecret::Secp256k1(shared) => shared.raw_secret_bytes(),
+            SharedSecret::P256(shared) => shared.raw_secret_bytes(),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct KeyPair {
     pub public_key: String,
     pub private_key: String,
+    curve: Curve,
+}
+
+/// Short fingerprint of an untagged public key: the first 8 bytes of its
+/// SHA-256 digest, hex encoded.
+pub fn public_key_fingerprint(untagged_public_key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(untagged_public_key)[..8])
+}
+
+/// Deprecated: use [`KeyPair::diffie_hellman`], which works on every curve.
+/// Kept for callers of the secp256k1-only API; fails for other curves.
+impl From<&KeyPair> for anyhow::Result<k256::SecretKey> {
+    fn from(key_pair: &KeyPair) -> Self {
+        if key_pair.curve != Curve::Secp256k1 {
+            return Err(CipherError::CurveMismatch {
+                expected: Curve::Secp256k1,
+                actual: key_pair.curve,
+            }
+            .into());
+        }
+        Ok(secret_key_from_hex(&key_pair.private_key)?)
+    }
 }
 
-impl From<&KeyPair> for Result<SecretKey> {
-    fn from(val: &KeyPair) -> std::result::Result<SecretKey, Error> {
-        Ok(SecretKey::from_slice(&hex::decode(&val.private_key)?)?)
+/// Deprecated: use the hex [`public_key`](KeyPair::public_key) field or
+/// [`KeyPair::to_public_key_untagged_bytes`], which work on every curve. Kept
+/// for callers of the secp256k1-only API; fails for other curves.
+impl From<&KeyPair> for anyhow::Result<k256::PublicKey> {
+    fn from(key_pair: &KeyPair) -> Self {
+        let secret: anyhow::Result<k256::SecretKey> = key_pair.into();
+        Ok(secret?.public_key())
     }
 }
 
-impl From<&KeyPair> for Result<PublicKey> {
-    fn from(value: &KeyPair) -> std::result::Result<PublicKey, Error> {
-        let secret: Result<SecretKey> = value.into();
-        let secret = secret?;
+fn secret_key_from_hex<C: CurveArithmetic>(private_key: &str) -> Result<SecretKey<C>, CipherError> {
+    SecretKey::from_slice(&hex::decode(private_key)?).map_err(|_| CipherError::InvalidPrivateKey)
+}
+
+fn public_key_untagged_bytes<C>(pub_key: &PublicKey<C>) -> Result<Vec<u8>, CipherError>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    let encoded_point = pub_key.to_encoded_point(false);
+    let x = encoded_point.x().ok_or(CipherError::InvalidPublicKey)?;
+    let y = encoded_point.y().ok_or(CipherError::InvalidPublicKey)?;
+
+    let mut result = Vec::new();
+    result.extend_from_slice(x);
+    result.extend_from_slice(y);
+    Ok(result)
+}
+
+fn public_key_from_untagged_bytes<C>(pub_key_slice: &[u8]) -> Result<PublicKey<C>, CipherError>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    if pub_key_slice.len() != UNTAGGED_PUBLIC_KEY_LENGTH {
+        return Err(CipherError::WrongKeyLength {
+            expected: UNTAGGED_PUBLIC_KEY_LENGTH,
+            actual: pub_key_slice.len(),
+        });
+    }
+
+    let mut tagged = Vec::with_capacity(pub_key_slice.len() + 1);
+    tagged.push(0x04);
+    tagged.extend_from_slice(pub_key_slice);
+
+    let encoded_point =
+        EncodedPoint::<C>::from_bytes(&tagged).map_err(|_| CipherError::InvalidPublicKey)?;
+    let pub_key = PublicKey::<C>::from_encoded_point(&encoded_point);
 
-        let pub_key_bytes = EncodedPoint::from(secret.public_key());
-        let pub_key = PublicKey::from_sec1_bytes(pub_key_bytes.as_ref())?;
-        Ok(pub_key)
+    if pub_key.is_none().into() {
+        return Err(CipherError::PointNotOnCurve);
     }
+
+    Ok(pub_key.expect("infallible"))
 }
 
-impl KeyPair {
-    pub fn from_secret_key(secret_key: &SecretKey) -> Result<Self> {
-        let pub_key_bytes = EncodedPoint::from(secret_key.public_key());
-        let pub_key = PublicKey::from_sec1_bytes(pub_key_bytes.as_ref())?;
-
-        let encoded_point = pub_key.to_encoded_point(false);
-        let x = encoded_point
-            .x()
-            .ok_or(anyhow::anyhow!("invalid public key"))?;
-        let y = encoded_point
-            .y()
-            .ok_or(anyhow::anyhow!("invalid public key"))?;
-
-        let mut untagged_public_key = Vec::new();
-        untagged_public_key.extend_from_slice(x);
-        untagged_public_key.extend_from_slice(y);
+fn diffie_hellman_on<C>(
+    private_key: &str,
+    pub_key_slice: &[u8],
+) -> Result<ecdh::SharedSecret<C>, CipherError>
+where
+    C: CurveArithmetic,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    FieldBytesSize<C>: ModulusSize,
+{
+    let secret = secret_key_from_hex::<C>(private_key)?;
+    let pub_key = public_key_from_untagged_bytes::<C>(pub_key_slice)?;
+
+    Ok(ecdh::diffie_hellman(
+        secret.to_nonzero_scalar(),
+        pub_key.as_affine(),
+    ))
+}
 
+impl KeyPair {
+    pub fn from_secret_key<C>(secret_key: &SecretKey<C>) -> Result<Self, CipherError>
+    where
+        C: EcdhCurve,
+        AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+        FieldBytesSize<C>: ModulusSize,
+    {
+        let untagged_public_key = public_key_untagged_bytes(&secret_key.public_key())?;
         let pub_key_hex = hex::encode_upper(untagged_public_key);
 
         let pri_key = secret_key.as_scalar_primitive();
@@ -58,46 +217,74 @@ impl KeyPair {
         Ok(KeyPair {
             public_key: pub_key_hex,
             private_key: pri_key_hex,
+            curve: C::CURVE,
         })
     }
 
-    pub fn from_private_key_str(private_key: &str) -> Result<Self> {
-        Self::from_secret_key(&SecretKey::from_slice(&hex::decode(private_key)?)?)
+    pub fn from_private_key_str(private_key: &str) -> Result<Self, CipherError> {
+        Self::from_private_key_str_with_curve(private_key, Curve::default())
     }
 
-    pub fn to_public_key_untagged_bytes(&self) -> Result<Vec<u8>> {
-        let pub_key: Result<PublicKey> = self.into();
-        let pub_key = pub_key?;
-
-        let result = pub_key.to_encoded_point(false);
-        let x = result.x().ok_or(anyhow::anyhow!("invalid public key"))?;
-        let y = result.y().ok_or(anyhow::anyhow!("invalid public key"))?;
+    pub fn from_private_key_str_with_curve(
+        private_key: &str,
+        curve: Curve,
+    ) -> Result<Self, CipherError> {
+        match curve {
+            Curve::Secp256k1 => {
+                Self::from_secret_key(&secret_key_from_hex::<Secp256k1>(private_key)?)
+            }
+            Curve::P256 => Self::from_secret_key(&secret_key_from_hex::<NistP256>(private_key)?),
+        }
+    }
 
-        let mut result = Vec::new();
-        result.extend_from_slice(x);
-        result.extend_from_slice(y);
-        Ok(result)
+    pub fn curve(&self) -> Curve {
+        self.curve
     }
 
-    pub fn random() -> Result<Self> {
-        let secret = SecretKey::new(ScalarPrimitive::random(&mut rand_core::OsRng));
-        Self::from_secret_key(&secret)
+    /// Public key derived from the private key, so it is right even if the
+    /// `public_key` field was changed.
+    pub fn to_public_key_untagged_bytes(&self) -> Result<Vec<u8>, CipherError> {
+        match self.curve {
+            Curve::Secp256k1 => public_key_untagged_bytes(
+                &secret_key_from_hex::<Secp256k1>(&self.private_key)?.public_key(),
+            ),
+            Curve::P256 => public_key_untagged_bytes(
+                &secret_key_from_hex::<NistP256>(&self.private_key)?.public_key(),
+            ),
+        }
     }
 
-    pub fn diffie_hellman(&self, pub_key_slice: &[u8]) -> Result<SharedSecret> {
-        let secret: Result<SecretKey> = self.into();
-        let secret = secret?;
+    pub fn fingerprint(&self) -> Result<String, CipherError> {
+        Ok(public_key_fingerprint(
+            &self.to_public_key_untagged_bytes()?,
+        ))
+    }
 
-        let pub_key =
-            PublicKey::from_encoded_point(&EncodedPoint::from_untagged_bytes(pub_key_slice.into()));
+    pub fn random() -> Result<Self, CipherError> {
+        Self::random_with_curve(Curve::default())
+    }
 
-        if pub_key.is_none().into() {
-            return Err(anyhow::anyhow!("invalid public key"));
+    pub fn random_with_curve(curve: Curve) -> Result<Self, CipherError> {
+        match curve {
+            Curve::Secp256k1 => Self::from_secret_key(&SecretKey::<Secp256k1>::new(
+                ScalarPrimitive::random(&mut rand_core::OsRng),
+            )),
+            Curve::P256 => Self::from_secret_key(&SecretKey::<NistP256>::new(
+                ScalarPrimitive::random(&mut rand_core::OsRng),
+            )),
         }
+    }
 
-        Ok(diffie_hellman(
-            secret.to_nonzero_scalar(),
-            pub_key.expect("infallible").as_affine(),
-        ))
+    pub fn diffie_hellman(&self, pub_key_slice: &[u8]) -> Result<SharedSecret, CipherError> {
+        match self.curve {
+            Curve::Secp256k1 => Ok(SharedSecret::Secp256k1(diffie_hellman_on(
+                &self.private_key,
+                pub_key_slice,
+            )?)),
+            Curve::P256 => Ok(SharedSecret::P256(diffie_hellman_on(
+                &self.private_key,
+                pub_key_slice,
+            )?)),
+        }
     }
 }
@@ -1,2 +1,4 @@
 pub mod aes_cfb_ecdh;
-pub mod key_pair;
\ No newline at end of file
+pub mod error;
+pub mod key_file;
+pub mod key_pair;
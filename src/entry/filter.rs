@@ -0,0 +1,195 @@
+use super::{parse_timestamp, GlogEntry, Level};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use std::str::FromStr;
+
+/// One end of a time range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    At(DateTime<FixedOffset>),
+    /// A time of day on any date, compared in each record's own offset.
+    TimeOfDay(NaiveTime),
+}
+
+impl TimeBound {
+    /// Whether `timestamp` is at or after this bound.
+    fn reached_by(&self, timestamp: &DateTime<FixedOffset>) -> bool {
+        match self {
+            TimeBound::At(at) => timestamp >= at,
+            TimeBound::TimeOfDay(time) => timestamp.time() >= *time,
+        }
+    }
+}
+
+impl FromStr for TimeBound {
+    type Err = String;
+
+    /// Accepts record timestamps (see [`parse_timestamp`]), `2023-08-03
+    /// 08:00[:00]` and `2023-08-03` in UTC, and `08:00[:00]` as a time of day.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(timestamp) = parse_timestamp(s) {
+            return Ok(TimeBound::At(timestamp));
+        }
+
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+            if let Ok(timestamp) = NaiveDateTime::parse_from_str(s, format) {
+                return Ok(TimeBound::At(timestamp.and_utc().fixed_offset()));
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(TimeBound::At(
+                date.and_time(NaiveTime::MIN).and_utc().fixed_offset(),
+            ));
+        }
+        for format in ["%H:%M:%S", "%H:%M"] {
+            if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+                return Ok(TimeBound::TimeOfDay(time));
+            }
+        }
+
+        Err(format!("invalid time: {}", s))
+    }
+}
+
+/// Conditions on [`GlogEntry`] fields, all of which must hold. A record
+/// missing a field that a condition looks at does not match.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    min_level: Option<Level>,
+    since: Option<TimeBound>,
+    until: Option<TimeBound>,
+    user_ids: Vec<String>,
+    namespaces: Vec<String>,
+}
+
+impl EntryFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_min_level(mut self, level: Level) -> Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    /// Keeps records at or after `since`.
+    pub fn with_since(mut self, since: TimeBound) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Keeps records before `until`.
+    pub fn with_until(mut self, until: TimeBound) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Keeps records of this user; repeat to allow several users.
+    pub fn with_user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_ids.push(user_id.into());
+        self
+    }
+
+    /// Keeps records of this namespace; repeat to allow several namespaces.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespaces.push(namespace.into());
+        self
+    }
+
+    /// Whether the filter lets every record through.
+    pub fn is_empty(&self) -> bool {
+        self.min_level.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+            && self.user_ids.is_empty()
+            && self.namespaces.is_empty()
+    }
+
+    pub fn matches(&self, entry: &GlogEntry) -> bool {
+        if let Some(min_level) = self.min_level {
+            if entry.level.is_none_or(|level| level < min_level) {
+                return false;
+            }
+        }
+
+        if self.since.is_some() || self.until.is_some() {
+            let timestamp = match &entry.timestamp {
+                Some(timestamp) => timestamp,
+                None => return false,
+            };
+            if self.since.is_some_and(|since| !since.reached_by(timestamp)) {
+                return false;
+            }
+            if self.until.is_some_and(|until| until.reached_by(timestamp)) {
+                return false;
+            }
+        }
+
+        if !self.user_ids.is_empty()
+            && !entry
+                .user_id
+                .as_ref()
+                .is_some_and(|user_id| self.user_ids.contains(user_id))
+        {
+            return false;
+        }
+
+        if !self.namespaces.is_empty()
+            && !entry
+                .namespace
+                .as_ref()
+                .is_some_and(|namespace| self.namespaces.contains(namespace))
+        {
+            return false;
+        }
+
+        true
+    }
+
+    /// Like [`matches`](Self::matches) on raw record content. Content that is
+    /// not a JSON object only passes an empty filter.
+    pub fn matches_content(&self, content: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        GlogEntry::parse(content).is_some_and(|entry| self.matches(&entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntryFilter, TimeBound};
+    use crate::entry::Level;
+
+    #[test]
+    fn test_entry_filter() {
+        let record = |level: u8, time: &str, user_id: &str| {
+            format!(
+                r#"{{"msg":"m","level":"{}","timestamp":"2023-08-03 {} +0000","userId":"{}","namespace":"ns"}}"#,
+                level, time, user_id
+            )
+        };
+
+        let filter = EntryFilter::new()
+            .with_min_level("warn".parse().unwrap())
+            .with_user_id("uid1")
+            .with_since("08:00".parse().unwrap())
+            .with_until("2023-08-03 09:00".parse().unwrap());
+
+        assert!(filter.matches_content(&record(3, "08:38:48", "uid1")));
+        assert!(filter.matches_content(&record(4, "08:00:00", "uid1")));
+        assert!(!filter.matches_content(&record(2, "08:38:48", "uid1")));
+        assert!(!filter.matches_content(&record(3, "08:38:48", "uid2")));
+        assert!(!filter.matches_content(&record(3, "07:59:59", "uid1")));
+        assert!(!filter.matches_content(&record(3, "09:00:00", "uid1")));
+        assert!(!filter.matches_content("plain text"));
+
+        assert!(EntryFilter::new().matches_content("plain text"));
+        assert!(EntryFilter::new()
+            .with_namespace("ns")
+            .with_min_level(Level::Verbose)
+            .matches_content(&record(0, "00:00:00", "uid3")));
+        assert!("2023-08-03T08:00:00+08:00".parse::<TimeBound>().is_ok());
+        assert!("tomorrow".parse::<TimeBound>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+pub mod filter;
+
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde_json::{Map, Value};
+use std::{fmt, str::FromStr};
+
+/// Timestamp format of glog records, e.g. `2023-08-03 08:38:48 +0000`.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+/// Severity of a record. Records store it as a number (`"level":"3"`);
+/// names are accepted as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Verbose = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+    Fatal = 5,
+}
+
+impl Level {
+    fn from_number(number: u64) -> Option<Self> {
+        match number {
+            0 => Some(Level::Verbose),
+            1 => Some(Level::Debug),
+            2 => Some(Level::Info),
+            3 => Some(Level::Warn),
+            4 => Some(Level::Error),
+            5 => Some(Level::Fatal),
+            _ => None,
+        }
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(number) => Self::from_number(number.as_u64()?),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(number) = s.parse() {
+            return Self::from_number(number).ok_or(format!("unknown level: {}", s));
+        }
+
+        match s.to_ascii_lowercase().as_str() {
+            "v" | "verbose" => Ok(Level::Verbose),
+            "d" | "debug" => Ok(Level::Debug),
+            "i" | "info" => Ok(Level::Info),
+            "w" | "warn" | "warning" => Ok(Level::Warn),
+            "e" | "error" => Ok(Level::Error),
+            "f" | "fatal" => Ok(Level::Fatal),
+            _ => Err(format!("unknown level: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Verbose => write!(f, "verbose"),
+            Level::Debug => write!(f, "debug"),
+            Level::Info => write!(f, "info"),
+            Level::Warn => write!(f, "warn"),
+            Level::Error => write!(f, "error"),
+            Level::Fatal => write!(f, "fatal"),
+        }
+    }
+}
+
+/// Parses a record timestamp: [`TIMESTAMP_FORMAT`], RFC 3339, or Unix time in
+/// milliseconds.
+pub fn parse_timestamp(s: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(timestamp) = DateTime::parse_from_str(s, TIMESTAMP_FORMAT) {
+        return Some(timestamp);
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(s) {
+        return Some(timestamp);
+    }
+    timestamp_from_millis(s.parse().ok()?)
+}
+
+fn timestamp_from_millis(millis: i64) -> Option<DateTime<FixedOffset>> {
+    Some(Utc.timestamp_millis_opt(millis).single()?.fixed_offset())
+}
+
+/// Keys of the fields that [`GlogEntry`] parses.
+pub const STANDARD_FIELDS: [&str; 5] = ["msg", "level", "timestamp", "userId", "namespace"];
+
+/// The standard fields of a JSON record. Missing or malformed fields are
+/// `None`; `fields` holds the whole object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlogEntry {
+    pub msg: Option<String>,
+    pub level: Option<Level>,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub user_id: Option<String>,
+    pub namespace: Option<String>,
+    pub fields: Map<String, Value>,
+}
+
+impl GlogEntry {
+    /// Parses record content, returning `None` unless it is a JSON object.
+    pub fn parse(content: &str) -> Option<Self> {
+        match serde_json::from_str(content) {
+            Ok(Value::Object(fields)) => Some(Self::from_fields(fields)),
+            _ => None,
+        }
+    }
+
+    pub fn from_fields(fields: Map<String, Value>) -> Self {
+        let string = |key: &str| match fields.get(key)? {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            _ => None,
+        };
+        let timestamp = match fields.get("timestamp") {
+            Some(Value::String(s)) => parse_timestamp(s),
+            Some(Value::Number(number)) => timestamp_from_millis(number.as_i64().unwrap_or(-1)),
+            _ => None,
+        };
+
+        Self {
+            msg: string("msg"),
+            level: fields.get("level").and_then(Level::from_value),
+            timestamp,
+            user_id: string("userId"),
+            namespace: string("namespace"),
+            fields,
+        }
+    }
+
+    /// The fields besides the [`STANDARD_FIELDS`], in record order.
+    pub fn extra_fields(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.fields
+            .iter()
+            .filter(|(key, _)| !STANDARD_FIELDS.contains(&key.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GlogEntry, Level};
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = GlogEntry::parse(
+            r#"{"msg":"save:1","level":"3","timestamp":"2023-08-03 08:38:48 +0000","userId":"uid12345","namespace":"namespace"}"#,
+        )
+        .unwrap();
+        assert_eq!(entry.msg.as_deref(), Some("save:1"));
+        assert_eq!(entry.level, Some(Level::Warn));
+        assert_eq!(
+            entry.timestamp.unwrap().to_rfc3339(),
+            "2023-08-03T08:38:48+00:00"
+        );
+        assert_eq!(entry.user_id.as_deref(), Some("uid12345"));
+        assert_eq!(entry.namespace.as_deref(), Some("namespace"));
+
+        let entry = GlogEntry::parse(r#"{"level":"error","timestamp":1691052000000}"#).unwrap();
+        assert_eq!(entry.level, Some(Level::Error));
+        assert_eq!(
+            entry.timestamp.unwrap().to_rfc3339(),
+            "2023-08-03T08:40:00+00:00"
+        );
+        assert_eq!(entry.msg, None);
+
+        assert!(GlogEntry::parse("plain text").is_none());
+    }
+}
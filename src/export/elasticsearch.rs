@@ -0,0 +1,347 @@
+use super::{Batch, BatchLimits, ExportError, HttpTransport, RecordSink, RetryPolicy};
+use crate::{
+    entry::GlogEntry,
+    format::{jsonl::json_record, RecordMeta},
+};
+use chrono::SecondsFormat;
+use serde_json::{json, Value};
+
+/// An Elasticsearch node on its default port.
+pub const DEFAULT_URL: &str = "http://localhost:9200";
+pub const DEFAULT_INDEX: &str = "glog";
+
+/// Field holding the record timestamp, as expected by Kibana.
+pub const TIMESTAMP_FIELD: &str = "@timestamp";
+
+/// Indexes records in Elasticsearch through the `_bulk` API. Documents are
+/// the JSON Lines objects of the records plus an `@timestamp` field.
+pub struct ElasticsearchSink {
+    bulk_url: String,
+    action: String,
+    transport: HttpTransport,
+    limits: BatchLimits,
+    /// Action and document lines of each record.
+    batch: Batch<String>,
+}
+
+impl ElasticsearchSink {
+    /// `url` is the base URL of the cluster, see [`DEFAULT_URL`].
+    pub fn new(url: &str) -> Self {
+        Self {
+            bulk_url: format!("{}/_bulk", url.trim_end_matches('/')),
+            action: Self::index_action(DEFAULT_INDEX),
+            transport: HttpTransport::default(),
+            limits: BatchLimits::default(),
+            batch: Batch::default(),
+        }
+    }
+
+    fn index_action(index: &str) -> String {
+        json!({ "index": { "_index": index } }).to_string()
+    }
+
+    /// Index or data stream to write to.
+    pub fn with_index(mut self, index: &str) -> Self {
+        self.action = Self::index_action(index);
+        self
+    }
+
+    /// Adds a request header, e.g. for authentication.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.transport.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_batch_limits(mut self, limits: BatchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.transport.retry = retry;
+        self
+    }
+}
+
+/// A document the bulk API did not index.
+struct ItemError {
+    /// Position of the document in the request.
+    item: usize,
+    /// Full queues answer `429`, as do `es_rejected_execution_exception`s.
+    retryable: bool,
+    reason: String,
+}
+
+/// The failed items of a bulk response.
+fn bulk_errors(response: &str) -> Vec<ItemError> {
+    let response: Value = match serde_json::from_str(response) {
+        Ok(response) => response,
+        Err(_) => return Vec::new(),
+    };
+    if response["errors"] != Value::Bool(true) {
+        return Vec::new();
+    }
+
+    let items = response["items"].as_array().map_or(&[][..], Vec::as_slice);
+    items
+        .iter()
+        .enumerate()
+        .filter_map(|(item, result)| {
+            let result = result.as_object()?.values().next()?;
+            let error = result.get("error")?;
+            let status = result["status"].as_u64().unwrap_or_default();
+            let kind = error["type"].as_str().unwrap_or("error");
+            Some(ItemError {
+                item,
+                retryable: status == 429
+                    || status >= 500
+                    || kind == "es_rejected_execution_exception",
+                reason: format!("{}: {}", kind, error["reason"].as_str().unwrap_or_default()),
+            })
+        })
+        .collect()
+}
+
+impl RecordSink for ElasticsearchSink {
+    fn write_record(&mut self, meta: &RecordMeta, content: &str) -> Result<(), ExportError> {
+        let mut document = json_record(meta, content);
+        if let Some(timestamp) = GlogEntry::parse(content).and_then(|entry| entry.timestamp) {
+            document.insert(
+                TIMESTAMP_FIELD.to_string(),
+                timestamp
+                    .to_rfc3339_opts(SecondsFormat::Millis, true)
+                    .into(),
+            );
+        }
+
+        let lines = format!("{}\n{}\n", self.action, Value::Object(document));
+        let bytes = lines.len();
+        self.batch.push(lines, bytes);
+        if self.batch.is_full(&self.limits) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records of a failed request stay buffered for the next flush, unless
+    /// they fill a batch, in which case they are dropped. When Elasticsearch accepts the request but rejects
+    /// some documents, only those rejected as too many requests are sent
+    /// again, following the retry policy; the others are dropped.
+    fn flush(&mut self) -> Result<(), ExportError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let records = self.batch.items.len();
+        let mut dropped = 0;
+        let mut first_dropped = None;
+        let mut retry = 0;
+        loop {
+            let body = self.batch.items.concat();
+            let response =
+                match self
+                    .transport
+                    .post(&self.bulk_url, "application/x-ndjson", body.as_bytes())
+                {
+                    Ok(response) => response,
+                    Err(error) => return Err(self.batch.retain_failed(&self.limits, error)),
+                };
+
+            let items = std::mem::take(&mut self.batch.items);
+            let mut resend = vec![false; items.len()];
+            let mut last_rejection = None;
+            for error in bulk_errors(&response) {
+                if error.retryable {
+                    if let Some(resend) = resend.get_mut(error.item) {
+                        *resend = true;
+                    }
+                    last_rejection = Some(error.reason);
+                } else {
+                    dropped += 1;
+                    first_dropped.get_or_insert(error.reason);
+                }
+            }
+            self.batch.clear();
+            for (lines, resend) in items.into_iter().zip(resend) {
+                if resend {
+                    let bytes = lines.len();
+                    self.batch.push(lines, bytes);
+                }
+            }
+
+            if !self.batch.is_empty() && retry < self.transport.retry.max_retries {
+                std::thread::sleep(self.transport.retry.backoff(retry));
+                retry += 1;
+                continue;
+            }
+
+            let remaining = self.batch.items.len();
+            let reason = match (first_dropped, last_rejection) {
+                (Some(reason), _) => format!(
+                    "{} of {} records failed, first: {}",
+                    dropped, records, reason
+                ),
+                (None, Some(reason)) => format!(
+                    "{} of {} records still rejected after {} retries: {}",
+                    remaining, records, retry, reason
+                ),
+                (None, None) => return Ok(()),
+            };
+            let error = ExportError::Rejected {
+                url: self.bulk_url.clone(),
+                reason,
+            };
+            return Err(self.batch.retain_failed(&self.limits, error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ElasticsearchSink;
+    use crate::{
+        export::{mock_server, BatchLimits, ExportError, RecordSink, RetryPolicy},
+        format::RecordMeta,
+    };
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[test]
+    fn test_bulk_index() {
+        let rejected = r#"{"errors":true,"items":[
+            {"index":{"status":400,"error":{"type":"mapper_parsing_exception","reason":"failed to parse field [level]"}}}
+        ]}"#;
+        let (url, requests) = mock_server::serve(vec![
+            (200, r#"{"errors":false,"items":[]}"#),
+            (200, rejected),
+        ]);
+        let mut sink = ElasticsearchSink::new(&format!("{}/", url))
+            .with_index("logs-app")
+            .with_batch_limits(BatchLimits {
+                max_records: 100,
+                max_bytes: 200,
+            });
+
+        let meta = RecordMeta {
+            file: "app.glog",
+            index: 0,
+            offset: 28,
+        };
+        // the second record goes over the byte limit
+        let content = r#"{"msg":"save","level":"3","timestamp":"2023-08-03 16:00:01 +0800"}"#;
+        sink.write_record(&meta, content).unwrap();
+        sink.write_record(&meta, "plain text").unwrap();
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.path, "/_bulk");
+        assert_eq!(request.header("content-type"), Some("application/x-ndjson"));
+        let body = String::from_utf8(request.body).unwrap();
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "index": { "_index": "logs-app" } }));
+        assert_eq!(lines[1]["@timestamp"], "2023-08-03T16:00:01.000+08:00");
+        assert_eq!(lines[1]["msg"], "save");
+        assert_eq!(lines[3]["raw"], "plain text");
+        assert!(lines[3].get("@timestamp").is_none());
+
+        sink.write_record(&meta, content).unwrap();
+        match sink.flush() {
+            Err(ExportError::Rejected { reason, .. }) => assert_eq!(
+                reason,
+                "1 of 1 records failed, first: mapper_parsing_exception: failed to parse field [level]"
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(sink.batch.is_empty());
+    }
+
+    #[test]
+    fn test_bulk_retry() {
+        let rejected = r#"{"errors":true,"items":[
+            {"index":{"status":201}},
+            {"index":{"status":429,"error":{"type":"es_rejected_execution_exception","reason":"rejected execution"}}},
+            {"index":{"status":400,"error":{"type":"mapper_parsing_exception","reason":"failed to parse field [level]"}}}
+        ]}"#;
+        let (url, requests) = mock_server::serve(vec![
+            (200, rejected),
+            (
+                200,
+                r#"{"errors":false,"items":[{"index":{"status":201}}]}"#,
+            ),
+        ]);
+        let mut sink = ElasticsearchSink::new(&url).with_retry(RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+
+        for index in 0..3 {
+            let meta = RecordMeta {
+                file: "app.glog",
+                index,
+                offset: 28,
+            };
+            sink.write_record(&meta, "text").unwrap();
+        }
+        match sink.flush() {
+            Err(ExportError::Rejected { reason, .. }) => assert_eq!(
+                reason,
+                "1 of 3 records failed, first: mapper_parsing_exception: failed to parse field [level]"
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(sink.batch.is_empty());
+
+        // only the document rejected for load is sent again
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            requests
+                .recv_timeout(timeout)
+                .unwrap()
+                .body
+                .split(|b| *b == b'\n')
+                .count(),
+            7
+        );
+        let retried = String::from_utf8(requests.recv_timeout(timeout).unwrap().body).unwrap();
+        let lines: Vec<Value> = retried
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["_index"], 1);
+    }
+
+    #[test]
+    fn test_bulk_retry_exhausted() {
+        let rejected = r#"{"errors":true,"items":[
+            {"index":{"status":429,"error":{"type":"es_rejected_execution_exception","reason":"rejected execution"}}}
+        ]}"#;
+        let (url, requests) = mock_server::serve(vec![(200, rejected), (200, rejected)]);
+        let mut sink = ElasticsearchSink::new(&url).with_retry(RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let meta = RecordMeta {
+            file: "app.glog",
+            index: 0,
+            offset: 28,
+        };
+
+        sink.write_record(&meta, "text").unwrap();
+        match sink.flush() {
+            Err(ExportError::Rejected { reason, .. }) => assert_eq!(
+                reason,
+                "1 of 1 records still rejected after 1 retries: es_rejected_execution_exception: rejected execution"
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // kept for the next flush
+        assert_eq!(sink.batch.items.len(), 1);
+        assert_eq!(requests.iter().take(2).count(), 2);
+    }
+}
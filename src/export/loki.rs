@@ -0,0 +1,241 @@
+use super::{Batch, BatchLimits, ExportError, HttpTransport, RecordSink, RetryPolicy};
+use crate::{
+    entry::GlogEntry,
+    format::{jsonl::json_record, RecordMeta},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Push API of a Loki instance on its default port.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:3100/loki/api/v1/push";
+
+type Labels = BTreeMap<String, String>;
+
+/// A log line with its stream labels and timestamp in Unix nanoseconds.
+struct Entry {
+    labels: Labels,
+    timestamp: i64,
+    line: String,
+}
+
+/// Pushes records to Grafana Loki as JSON Lines log lines, in streams
+/// labelled by `namespace` and `level` besides the fixed labels.
+pub struct LokiSink {
+    endpoint: String,
+    labels: Labels,
+    transport: HttpTransport,
+    limits: BatchLimits,
+    batch: Batch<Entry>,
+}
+
+impl LokiSink {
+    /// `endpoint` is the full URL of the push API, see [`DEFAULT_ENDPOINT`].
+    /// Streams have the label `job="glog"` unless it is replaced.
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            labels: Labels::from([("job".to_string(), "glog".to_string())]),
+            transport: HttpTransport::default(),
+            limits: BatchLimits::default(),
+            batch: Batch::default(),
+        }
+    }
+
+    /// Adds or replaces a label of every stream.
+    pub fn with_label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(name.into(), value.into());
+        self
+    }
+
+    /// Adds a request header, e.g. `X-Scope-OrgID` for multi-tenant Loki.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.transport.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_batch_limits(mut self, limits: BatchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.transport.retry = retry;
+        self
+    }
+
+    /// One stream per label set, with its lines in time order.
+    fn request_body(&self) -> Value {
+        let mut streams: BTreeMap<&Labels, Vec<&Entry>> = BTreeMap::new();
+        for entry in &self.batch.items {
+            streams.entry(&entry.labels).or_default().push(entry);
+        }
+
+        let streams: Vec<_> = streams
+            .into_iter()
+            .map(|(labels, mut entries)| {
+                entries.sort_by_key(|entry| entry.timestamp);
+                let values: Vec<_> = entries
+                    .iter()
+                    .map(|entry| json!([entry.timestamp.to_string(), entry.line]))
+                    .collect();
+                json!({ "stream": labels, "values": values })
+            })
+            .collect();
+        json!({ "streams": streams })
+    }
+}
+
+impl RecordSink for LokiSink {
+    /// Records without a timestamp get the current time.
+    fn write_record(&mut self, meta: &RecordMeta, content: &str) -> Result<(), ExportError> {
+        let mut labels = self.labels.clone();
+        let mut timestamp = None;
+        if let Some(entry) = GlogEntry::parse(content) {
+            if let Some(namespace) = entry.namespace {
+                labels.insert("namespace".to_string(), namespace);
+            }
+            if let Some(level) = entry.level {
+                labels.insert("level".to_string(), level.to_string());
+            }
+            timestamp = entry.timestamp.and_then(|t| t.timestamp_nanos_opt());
+        }
+        let timestamp = timestamp.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as i64
+        });
+
+        let line = Value::Object(json_record(meta, content)).to_string();
+        let bytes = line.len();
+        self.batch.push(
+            Entry {
+                labels,
+                timestamp,
+                line,
+            },
+            bytes,
+        );
+        if self.batch.is_full(&self.limits) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records of a failed request stay buffered for the next flush, unless
+    /// they fill a batch, in which case they are dropped.
+    fn flush(&mut self) -> Result<(), ExportError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let body = serde_json::to_vec(&self.request_body()).map_err(std::io::Error::from)?;
+        if let Err(error) = self
+            .transport
+            .post(&self.endpoint, "application/json", &body)
+        {
+            return Err(self.batch.retain_failed(&self.limits, error));
+        }
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LokiSink;
+    use crate::{
+        export::{mock_server, ExportError, RecordSink, RetryPolicy},
+        format::RecordMeta,
+    };
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[test]
+    fn test_loki_push() {
+        // the first attempt fails and is retried
+        let (url, requests) = mock_server::serve(vec![(503, ""), (204, "")]);
+        let mut sink = LokiSink::new(format!("{}/loki/api/v1/push", url))
+            .with_label("app", "driver")
+            .with_retry(RetryPolicy {
+                max_retries: 1,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            });
+
+        let log = |msg: &str, namespace: &str, second: u32| {
+            format!(
+                r#"{{"msg":"{}","level":"2","timestamp":"2023-08-03 08:00:{:02} +0000","namespace":"{}"}}"#,
+                msg, second, namespace
+            )
+        };
+        for (index, content) in [
+            log("b", "order", 2),
+            log("c", "map", 3),
+            log("a", "order", 1),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let meta = RecordMeta {
+                file: "app.glog",
+                index,
+                offset: 0,
+            };
+            sink.write_record(&meta, content).unwrap();
+        }
+        sink.flush().unwrap();
+
+        let timeout = Duration::from_secs(5);
+        let first = requests.recv_timeout(timeout).unwrap();
+        let second = requests.recv_timeout(timeout).unwrap();
+        assert_eq!(first.body, second.body);
+        assert_eq!(second.path, "/loki/api/v1/push");
+
+        let body: Value = serde_json::from_slice(&second.body).unwrap();
+        let streams = body["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(
+            streams[0]["stream"],
+            json!({ "app": "driver", "job": "glog", "level": "info", "namespace": "map" })
+        );
+        let order = &streams[1];
+        assert_eq!(order["stream"]["namespace"], "order");
+        assert_eq!(order["values"][0][0], "1691049601000000000");
+        let line: Value = serde_json::from_str(order["values"][0][1].as_str().unwrap()).unwrap();
+        assert_eq!(line["msg"], "a");
+        assert_eq!(line["_index"], 2);
+    }
+
+    #[test]
+    fn test_loki_retry_exhausted() {
+        let (url, requests) = mock_server::serve(vec![(500, ""), (500, ""), (204, "")]);
+        let mut sink = LokiSink::new(url).with_retry(RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let meta = RecordMeta {
+            file: "app.glog",
+            index: 0,
+            offset: 28,
+        };
+
+        sink.write_record(&meta, "a").unwrap();
+        match sink.flush() {
+            Err(ExportError::HttpStatus { status: 500, .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(sink.batch.items.len(), 1);
+
+        // the kept record goes out with the next flush
+        sink.write_record(&meta, "b").unwrap();
+        sink.flush().unwrap();
+        let last = requests.iter().nth(2).unwrap();
+        let body: Value = serde_json::from_slice(&last.body).unwrap();
+        assert_eq!(body["streams"][0]["values"].as_array().unwrap().len(), 2);
+    }
+}
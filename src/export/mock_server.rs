@@ -0,0 +1,80 @@
+//! A minimal HTTP server for testing exporters against.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+pub struct Request {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answers requests with the given statuses and bodies in turn, one request
+/// per connection, and passes each request on. Returns the base URL.
+pub fn serve(responses: Vec<(u16, &'static str)>) -> (String, Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = channel();
+
+    thread::spawn(move || {
+        for (status, response_body) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+
+            let mut headers = Vec::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => {
+                        headers.push((name.to_string(), value.trim().to_string()))
+                    }
+                    None => break,
+                }
+            }
+
+            let length = headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .map_or(0, |(_, value)| value.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response_body.len(),
+                response_body
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+
+            let request = Request {
+                path,
+                headers,
+                body,
+            };
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    (url, receiver)
+}
@@ -0,0 +1,236 @@
+#[cfg(feature = "export")]
+pub mod elasticsearch;
+#[cfg(feature = "export")]
+pub mod loki;
+#[cfg(feature = "export")]
+pub mod otlp;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+#[cfg(all(test, feature = "export"))]
+pub(crate) mod mock_server;
+
+use crate::format::RecordMeta;
+use std::{io, time::Duration};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("io error")]
+    IoError(#[from] io::Error),
+
+    #[error("{url} answered {status}: {body}")]
+    HttpStatus {
+        url: String,
+        status: u16,
+        body: String,
+    },
+
+    #[error("request failed")]
+    Transport {
+        url: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("{url} rejected records: {reason}")]
+    Rejected { url: String, reason: String },
+
+    /// A failed flush that dropped its records instead of keeping them.
+    #[error("{records} buffered records dropped")]
+    Dropped {
+        records: usize,
+        #[source]
+        source: Box<ExportError>,
+    },
+
+    #[cfg(feature = "parquet")]
+    #[error("parquet error")]
+    Parquet(#[from] ::parquet::errors::ParquetError),
+}
+
+/// Destination of decoded records that may buffer them, such as a log
+/// backend or a columnar file. Unlike a [`RecordFormat`](crate::format::RecordFormat)
+/// it owns its output.
+pub trait RecordSink {
+    fn write_record(&mut self, meta: &RecordMeta, content: &str) -> Result<(), ExportError>;
+
+    /// Sends or writes all buffered records. A sink may drop the records of
+    /// a failed flush rather than buffer without bound; it then says so with
+    /// [`ExportError::Dropped`].
+    fn flush(&mut self) -> Result<(), ExportError>;
+
+    /// Flushes and completes the output, e.g. with a file footer. No
+    /// records may be written afterwards.
+    fn finish(&mut self) -> Result<(), ExportError> {
+        self.flush()
+    }
+}
+
+/// When a sink sends its buffered records: as soon as either limit is
+/// reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimits {
+    pub max_records: usize,
+    /// Encoded size of the buffered records.
+    pub max_bytes: usize,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_records: 1000,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Buffered records of a sink, in their encoded form.
+#[cfg(feature = "export")]
+#[derive(Debug)]
+pub(crate) struct Batch<T> {
+    pub items: Vec<T>,
+    pub bytes: usize,
+}
+
+#[cfg(feature = "export")]
+impl<T> Default for Batch<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            bytes: 0,
+        }
+    }
+}
+
+#[cfg(feature = "export")]
+impl<T> Batch<T> {
+    pub fn push(&mut self, item: T, bytes: usize) {
+        self.items.push(item);
+        self.bytes += bytes;
+    }
+
+    pub fn is_full(&self, limits: &BatchLimits) -> bool {
+        self.items.len() >= limits.max_records || self.bytes >= limits.max_bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.bytes = 0;
+    }
+
+    /// Keeps the records of a failed request for the next flush unless they
+    /// fill a batch, so that a backend that stays down costs one request per
+    /// batch and holds no more than one. Returns `error`, wrapped in
+    /// [`ExportError::Dropped`] if the records were dropped.
+    pub fn retain_failed(&mut self, limits: &BatchLimits, error: ExportError) -> ExportError {
+        if !self.is_full(limits) {
+            return error;
+        }
+        let records = self.items.len();
+        self.clear();
+        ExportError::Dropped {
+            records,
+            source: Box::new(error),
+        }
+    }
+}
+
+/// How often and how patiently a failed request is repeated. Connection
+/// errors, `429` and `5xx` answers are retried, waiting twice as long after
+/// each attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+#[cfg(feature = "export")]
+impl RetryPolicy {
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// POSTs batches with extra headers and retries.
+#[cfg(feature = "export")]
+#[derive(Clone)]
+pub(crate) struct HttpTransport {
+    agent: ureq::Agent,
+    pub headers: Vec<(String, String)>,
+    pub retry: RetryPolicy,
+}
+
+#[cfg(feature = "export")]
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self {
+            agent: ureq::Agent::new(),
+            headers: Vec::new(),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+#[cfg(feature = "export")]
+impl HttpTransport {
+    /// Sends `body` and returns the response body, failing on non-2xx
+    /// answers once the retries are used up.
+    pub fn post(&self, url: &str, content_type: &str, body: &[u8]) -> Result<String, ExportError> {
+        let mut retry = 0;
+        loop {
+            let error = match self.post_once(url, content_type, body) {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+
+            let retryable = match &error {
+                ExportError::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+                ExportError::Transport { .. } => true,
+                _ => false,
+            };
+            if !retryable || retry >= self.retry.max_retries {
+                return Err(error);
+            }
+            std::thread::sleep(self.retry.backoff(retry));
+            retry += 1;
+        }
+    }
+
+    fn post_once(&self, url: &str, content_type: &str, body: &[u8]) -> Result<String, ExportError> {
+        let mut request = self.agent.post(url).set("Content-Type", content_type);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+
+        match request.send_bytes(body) {
+            Ok(response) => Ok(response.into_string()?),
+            Err(ureq::Error::Status(status, response)) => Err(ExportError::HttpStatus {
+                url: url.to_string(),
+                status,
+                body: response.into_string().unwrap_or_default(),
+            }),
+            Err(e) => Err(ExportError::Transport {
+                url: url.to_string(),
+                source: Box::new(e),
+            }),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use super::{Batch, BatchLimits, ExportError, HttpTransport, RecordSink, RetryPolicy};
+use crate::{
+    entry::{GlogEntry, Level},
+    format::RecordMeta,
+};
+use serde_json::{json, value::RawValue, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Logs path of a collector on the default OTLP/HTTP port.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:4318/v1/logs";
+pub const DEFAULT_SERVICE_NAME: &str = "glog";
+
+/// Attribute holding the name of the decoded file.
+pub const FILE_ATTRIBUTE: &str = "log.file.name";
+/// Attribute holding the offset of the record in its file.
+pub const OFFSET_ATTRIBUTE: &str = "glog.offset";
+
+/// OTLP severity number and text of a glog level.
+fn severity(level: Level) -> (u8, &'static str) {
+    match level {
+        Level::Verbose => (1, "TRACE"),
+        Level::Debug => (5, "DEBUG"),
+        Level::Info => (9, "INFO"),
+        Level::Warn => (13, "WARN"),
+        Level::Error => (17, "ERROR"),
+        Level::Fatal => (21, "FATAL"),
+    }
+}
+
+/// A JSON value as an OTLP `AnyValue`. 64-bit integers are strings, as in the
+/// protobuf JSON mapping.
+fn any_value(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({}),
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(number) => match number.as_i64() {
+            Some(int) => json!({ "intValue": int.to_string() }),
+            None => json!({ "doubleValue": number.as_f64() }),
+        },
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Array(values) => {
+            json!({ "arrayValue": { "values": values.iter().map(any_value).collect::<Vec<_>>() } })
+        }
+        Value::Object(fields) => json!({
+            "kvlistValue": {
+                "values": fields.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<_>>()
+            }
+        }),
+    }
+}
+
+fn attribute(key: &str, value: &Value) -> Value {
+    json!({ "key": key, "value": any_value(value) })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// Maps a record to an OTLP/JSON `LogRecord`: `msg` becomes the body, the
+/// level the severity, and `userId`, `namespace` and the other fields become
+/// attributes. Content that is not a JSON object is the body as is.
+pub fn log_record(meta: &RecordMeta, content: &str) -> Value {
+    let mut record = json!({ "observedTimeUnixNano": unix_nanos(SystemTime::now()) });
+    let mut attributes = vec![
+        attribute(FILE_ATTRIBUTE, &meta.file.into()),
+        attribute(OFFSET_ATTRIBUTE, &meta.offset.into()),
+    ];
+
+    let entry = GlogEntry::parse(content);
+    let body = entry
+        .as_ref()
+        .and_then(|entry| entry.msg.as_deref())
+        .unwrap_or(content);
+    record["body"] = json!({ "stringValue": body });
+
+    if let Some(entry) = &entry {
+        if let Some(nanos) = entry.timestamp.and_then(|t| t.timestamp_nanos_opt()) {
+            record["timeUnixNano"] = nanos.to_string().into();
+        }
+        if let Some(level) = entry.level {
+            let (number, text) = severity(level);
+            record["severityNumber"] = number.into();
+            record["severityText"] = text.into();
+        }
+        if let Some(user_id) = &entry.user_id {
+            attributes.push(attribute("userId", &user_id.as_str().into()));
+        }
+        if let Some(namespace) = &entry.namespace {
+            attributes.push(attribute("namespace", &namespace.as_str().into()));
+        }
+        for (key, value) in entry.extra_fields() {
+            attributes.push(attribute(key, value));
+        }
+    }
+
+    record["attributes"] = attributes.into();
+    record
+}
+
+/// Sends records to an OpenTelemetry collector over OTLP/HTTP with JSON
+/// encoding, in batches.
+pub struct OtlpExporter {
+    endpoint: String,
+    service_name: String,
+    transport: HttpTransport,
+    limits: BatchLimits,
+    batch: Batch<Box<RawValue>>,
+}
+
+impl OtlpExporter {
+    /// `endpoint` is the full URL of the logs path, see [`DEFAULT_ENDPOINT`].
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            service_name: DEFAULT_SERVICE_NAME.to_string(),
+            transport: HttpTransport::default(),
+            limits: BatchLimits::default(),
+            batch: Batch::default(),
+        }
+    }
+
+    /// Value of the `service.name` resource attribute.
+    pub fn with_service_name(mut self, service_name: impl Into<String>) -> Self {
+        self.service_name = service_name.into();
+        self
+    }
+
+    /// Adds a request header, e.g. for authentication.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.transport.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_batch_limits(mut self, limits: BatchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.transport.retry = retry;
+        self
+    }
+
+    fn request_body(&self) -> Value {
+        json!({
+            "resourceLogs": [{
+                "resource": {
+                    "attributes": [attribute("service.name", &self.service_name.as_str().into())]
+                },
+                "scopeLogs": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                    "logRecords": self.batch.items,
+                }]
+            }]
+        })
+    }
+}
+
+impl RecordSink for OtlpExporter {
+    fn write_record(&mut self, meta: &RecordMeta, content: &str) -> Result<(), ExportError> {
+        let record = RawValue::from_string(log_record(meta, content).to_string())
+            .map_err(std::io::Error::from)?;
+        let bytes = record.get().len();
+        self.batch.push(record, bytes);
+        if self.batch.is_full(&self.limits) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records of a failed request stay buffered for the next flush, unless
+    /// they fill a batch, in which case they are dropped.
+    fn flush(&mut self) -> Result<(), ExportError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let body = serde_json::to_vec(&self.request_body()).map_err(std::io::Error::from)?;
+        if let Err(error) = self
+            .transport
+            .post(&self.endpoint, "application/json", &body)
+        {
+            return Err(self.batch.retain_failed(&self.limits, error));
+        }
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OtlpExporter;
+    use crate::{
+        export::{mock_server, BatchLimits, ExportError, RecordSink, RetryPolicy},
+        format::RecordMeta,
+    };
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[test]
+    fn test_otlp_export() {
+        let (url, requests) = mock_server::serve(vec![(200, "{}"), (503, "{}")]);
+        let mut exporter = OtlpExporter::new(format!("{}/v1/logs", url))
+            .with_service_name("app")
+            .with_header("Authorization", "Bearer token")
+            .with_batch_limits(BatchLimits {
+                max_records: 2,
+                ..Default::default()
+            })
+            .with_retry(RetryPolicy {
+                max_retries: 0,
+                ..Default::default()
+            });
+
+        let meta = |index| RecordMeta {
+            file: "app.glog",
+            index,
+            offset: 28 + index as i64 * 100,
+        };
+        exporter
+            .write_record(
+                &meta(0),
+                r#"{"msg":"save","level":"3","timestamp":"2023-08-03 08:00:01 +0000","userId":"u1","namespace":"order","retry":2}"#,
+            )
+            .unwrap();
+        exporter.write_record(&meta(1), "plain text").unwrap();
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.path, "/v1/logs");
+        assert_eq!(request.header("authorization"), Some("Bearer token"));
+        assert_eq!(request.header("content-type"), Some("application/json"));
+
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let resource_logs = &body["resourceLogs"][0];
+        assert_eq!(
+            resource_logs["resource"]["attributes"][0],
+            json!({ "key": "service.name", "value": { "stringValue": "app" } })
+        );
+
+        let records = &resource_logs["scopeLogs"][0]["logRecords"];
+        let first = &records[0];
+        assert_eq!(first["body"], json!({ "stringValue": "save" }));
+        assert_eq!(first["timeUnixNano"], "1691049601000000000");
+        assert_eq!(first["severityNumber"], 13);
+        assert_eq!(first["severityText"], "WARN");
+        assert_eq!(
+            first["attributes"],
+            json!([
+                { "key": "log.file.name", "value": { "stringValue": "app.glog" } },
+                { "key": "glog.offset", "value": { "intValue": "28" } },
+                { "key": "userId", "value": { "stringValue": "u1" } },
+                { "key": "namespace", "value": { "stringValue": "order" } },
+                { "key": "retry", "value": { "intValue": "2" } },
+            ])
+        );
+        assert_eq!(records[1]["body"], json!({ "stringValue": "plain text" }));
+        assert!(records[1].get("severityNumber").is_none());
+
+        exporter.write_record(&meta(2), "kept").unwrap();
+        match exporter.flush() {
+            Err(ExportError::HttpStatus { status: 503, .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(exporter.batch.items.len(), 1);
+    }
+
+    #[test]
+    fn test_otlp_retry_exhausted() {
+        let (url, requests) =
+            mock_server::serve(vec![(503, "{}"), (503, "{}"), (429, "{}"), (429, "{}")]);
+        let mut exporter = OtlpExporter::new(url)
+            .with_batch_limits(BatchLimits {
+                max_records: 2,
+                ..Default::default()
+            })
+            .with_retry(RetryPolicy {
+                max_retries: 1,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            });
+        let meta = RecordMeta {
+            file: "app.glog",
+            index: 0,
+            offset: 28,
+        };
+
+        exporter.write_record(&meta, "a").unwrap();
+        match exporter.flush() {
+            Err(ExportError::HttpStatus { status: 503, .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(exporter.batch.items.len(), 1);
+
+        // a full batch that cannot be sent is dropped
+        match exporter.write_record(&meta, "b") {
+            Err(ExportError::Dropped { records: 2, source })
+                if matches!(*source, ExportError::HttpStatus { status: 429, .. }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(exporter.batch.is_empty());
+        assert_eq!(requests.iter().take(4).count(), 4);
+    }
+}
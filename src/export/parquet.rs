@@ -0,0 +1,260 @@
+use super::{ExportError, RecordSink};
+use crate::{entry::GlogEntry, format::RecordMeta};
+use arrow_array::{
+    builder::{Int64Builder, StringBuilder, TimestampMicrosecondBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use serde_json::{Map, Value};
+use std::{io::Write, sync::Arc};
+
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Columns of the exported files. Content that is not a JSON object is the
+/// `msg` as is, with the other entry columns null.
+pub fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("level", DataType::Utf8, true),
+        Field::new("userId", DataType::Utf8, true),
+        Field::new("namespace", DataType::Utf8, true),
+        Field::new("msg", DataType::Utf8, true),
+        Field::new("file", DataType::Utf8, false),
+        Field::new("offset", DataType::Int64, false),
+        // the fields besides the standard ones, as a JSON object
+        Field::new("extra", DataType::Utf8, true),
+    ])
+}
+
+/// Rows of the row group being built.
+#[derive(Default)]
+struct Columns {
+    timestamp: TimestampMicrosecondBuilder,
+    level: StringBuilder,
+    user_id: StringBuilder,
+    namespace: StringBuilder,
+    msg: StringBuilder,
+    file: StringBuilder,
+    offset: Int64Builder,
+    extra: StringBuilder,
+    rows: usize,
+}
+
+impl Columns {
+    fn push(&mut self, meta: &RecordMeta, content: &str) {
+        match GlogEntry::parse(content) {
+            Some(entry) => {
+                let extra: Map<_, _> = entry
+                    .extra_fields()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                self.timestamp
+                    .append_option(entry.timestamp.map(|t| t.timestamp_micros()));
+                self.level
+                    .append_option(entry.level.map(|level| level.to_string()));
+                self.user_id.append_option(entry.user_id);
+                self.namespace.append_option(entry.namespace);
+                self.msg.append_option(entry.msg);
+                self.extra
+                    .append_option((!extra.is_empty()).then(|| Value::Object(extra).to_string()));
+            }
+            None => {
+                self.timestamp.append_null();
+                self.level.append_null();
+                self.user_id.append_null();
+                self.namespace.append_null();
+                self.msg.append_value(content);
+                self.extra.append_null();
+            }
+        }
+        self.file.append_value(meta.file);
+        self.offset.append_value(meta.offset);
+        self.rows += 1;
+    }
+
+    /// Takes the rows, leaving the builders empty.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch, ExportError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.timestamp.finish().with_timezone("UTC")),
+            Arc::new(self.level.finish()),
+            Arc::new(self.user_id.finish()),
+            Arc::new(self.namespace.finish()),
+            Arc::new(self.msg.finish()),
+            Arc::new(self.file.finish()),
+            Arc::new(self.offset.finish()),
+            Arc::new(self.extra.finish()),
+        ];
+        self.rows = 0;
+        RecordBatch::try_new(schema.clone(), columns)
+            .map_err(|e| ExportError::from(ParquetError::from(e)))
+    }
+}
+
+/// Writes records as a zstd-compressed Parquet file with the columns of
+/// [`schema`]. [`finish`](RecordSink::finish) must be called to write the
+/// file footer.
+pub struct ParquetSink<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    columns: Columns,
+    row_group_size: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    pub fn new(output: W) -> Result<Self, ExportError> {
+        let schema = Arc::new(schema());
+        // row groups are cut by the sink, not by the writer
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(usize::MAX)
+            .build();
+        let writer = ArrowWriter::try_new(output, schema.clone(), Some(properties))?;
+        Ok(Self {
+            writer,
+            schema,
+            columns: Columns::default(),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+        })
+    }
+
+    /// Rows per row group. Each [`flush`](RecordSink::flush) also ends one.
+    pub fn with_row_group_size(mut self, row_group_size: usize) -> Self {
+        self.row_group_size = row_group_size.max(1);
+        self
+    }
+
+    /// Finishes the file and returns the output.
+    pub fn into_inner(mut self) -> Result<W, ExportError> {
+        self.flush()?;
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+impl<W: Write + Send> RecordSink for ParquetSink<W> {
+    fn write_record(&mut self, meta: &RecordMeta, content: &str) -> Result<(), ExportError> {
+        self.columns.push(meta, content);
+        if self.columns.rows >= self.row_group_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows as a row group.
+    fn flush(&mut self) -> Result<(), ExportError> {
+        if self.columns.rows == 0 {
+            return Ok(());
+        }
+
+        let batch = self.columns.finish(&self.schema)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ExportError> {
+        self.flush()?;
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParquetSink;
+    use crate::{export::RecordSink, format::RecordMeta};
+    use arrow_array::{
+        cast::AsArray,
+        types::{Int64Type, TimestampMicrosecondType},
+        Array,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::File;
+
+    #[test]
+    fn test_parquet_export() {
+        let path = std::env::temp_dir().join(format!("glog-export-{}.parquet", std::process::id()));
+        let mut sink = ParquetSink::new(File::create(&path).unwrap())
+            .unwrap()
+            .with_row_group_size(2);
+
+        let contents = [
+            r#"{"msg":"save","level":"3","timestamp":"2023-08-03 08:00:01 +0000","userId":"u1","namespace":"order","retry":2}"#,
+            r#"{"msg":"load","level":"2"}"#,
+            "plain text",
+        ];
+        for (index, content) in contents.iter().enumerate() {
+            let meta = RecordMeta {
+                file: "app.glog",
+                index,
+                offset: 28 + index as i64 * 100,
+            };
+            sink.write_record(&meta, content).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 2);
+        let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let strings = |name: &str| {
+            let mut values = Vec::new();
+            for batch in &batches {
+                let column = batch.column_by_name(name).unwrap().as_string::<i32>();
+                values.extend(
+                    (0..column.len())
+                        .map(|i| column.is_valid(i).then(|| column.value(i).to_string())),
+                );
+            }
+            values
+        };
+        assert_eq!(
+            strings("msg"),
+            [
+                Some("save".into()),
+                Some("load".into()),
+                Some("plain text".into())
+            ]
+        );
+        assert_eq!(
+            strings("level"),
+            [Some("warn".into()), Some("info".into()), None]
+        );
+        assert_eq!(strings("userId"), [Some("u1".into()), None, None]);
+        assert_eq!(
+            strings("extra"),
+            [Some(r#"{"retry":2}"#.into()), None, None]
+        );
+        assert_eq!(
+            strings("file"),
+            [
+                Some("app.glog".into()),
+                Some("app.glog".into()),
+                Some("app.glog".into())
+            ]
+        );
+
+        let batch = &batches[0];
+        let timestamps = batch
+            .column_by_name("timestamp")
+            .unwrap()
+            .as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(timestamps.value(0), 1_691_049_601_000_000);
+        assert!(timestamps.is_null(1));
+        let offsets = batch
+            .column_by_name("offset")
+            .unwrap()
+            .as_primitive::<Int64Type>();
+        assert_eq!(offsets.values(), &[28, 128, 228]);
+    }
+}
@@ -0,0 +1,175 @@
+use super::{
+    jsonl::{json_record, FILE_KEY, OFFSET_KEY, RAW_KEY},
+    value_text, RecordFormat, RecordMeta,
+};
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+/// Columns written when none are selected. Records that are not JSON
+/// objects only fill `raw`.
+pub const DEFAULT_COLUMNS: [&str; 8] = [
+    FILE_KEY,
+    OFFSET_KEY,
+    "timestamp",
+    "level",
+    "userId",
+    "namespace",
+    "msg",
+    RAW_KEY,
+];
+
+/// Quotes a field if it holds a separator, quote or line break, as in
+/// RFC 4180, so that multi-line messages stay in one cell. Fields starting
+/// with `=`, `@`, a tab or a carriage return get a leading `'`, so that
+/// spreadsheets do not run them as formulas; so do fields starting with `+`
+/// or `-` unless they are numbers.
+fn escape(field: &str) -> Cow<'_, str> {
+    let formula = field.starts_with(['=', '@', '\t', '\r'])
+        || (field.starts_with(['+', '-']) && field.parse::<f64>().is_err());
+    let field = if formula {
+        Cow::Owned(format!("'{}", field))
+    } else {
+        Cow::Borrowed(field)
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        field
+    }
+}
+
+/// Comma-separated values with a header row. Columns are keys of the
+/// [`json_record`] object of a record; missing fields are empty.
+pub struct CsvFormat {
+    columns: Vec<String>,
+    header_written: bool,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_COLUMNS
+                .iter()
+                .map(|column| column.to_string())
+                .collect(),
+        )
+    }
+}
+
+impl CsvFormat {
+    pub fn new(columns: Vec<String>) -> Self {
+        Self {
+            columns,
+            header_written: false,
+        }
+    }
+
+    fn write_row<'a>(
+        output: &mut dyn Write,
+        fields: impl Iterator<Item = Cow<'a, str>>,
+    ) -> io::Result<()> {
+        for (i, field) in fields.enumerate() {
+            if i > 0 {
+                output.write_all(b",")?;
+            }
+            output.write_all(escape(&field).as_bytes())?;
+        }
+        output.write_all(b"\n")
+    }
+}
+
+impl RecordFormat for CsvFormat {
+    /// Writes the column names, unless they were written already.
+    fn write_header(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        if !self.header_written {
+            Self::write_row(
+                output,
+                self.columns.iter().map(|c| Cow::Borrowed(c.as_str())),
+            )?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        output: &mut dyn Write,
+        meta: &RecordMeta,
+        content: &str,
+    ) -> io::Result<()> {
+        self.write_header(output)?;
+        let object = json_record(meta, content);
+        let fields = self.columns.iter().map(|column| {
+            object
+                .get(column)
+                .map_or(Cow::Borrowed(""), |value| value_text(value))
+        });
+        Self::write_row(output, fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvFormat;
+    use crate::format::{RecordFormat, RecordMeta};
+
+    #[test]
+    fn test_csv_format() {
+        let mut format = CsvFormat::new(vec![
+            "_offset".to_string(),
+            "level".to_string(),
+            "msg".to_string(),
+            "tags".to_string(),
+            "raw".to_string(),
+        ]);
+        let mut output = Vec::new();
+        format.write_header(&mut output).unwrap();
+        for (index, content) in [
+            r#"{"msg":"line 1\nline \"2\", done","level":"3","tags":["a","b"]}"#,
+            r#"{"msg":"plain","level":null}"#,
+            "not, json",
+            r#"{"msg":"=HYPERLINK(\"http://x\")","level":"-1"}"#,
+            "@SUM(A1)",
+            "-2+3",
+            r#"{"msg":"\tcmd","level":"+1"}"#,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let meta = RecordMeta {
+                file: "app.glog",
+                index,
+                offset: 28 + index as i64 * 100,
+            };
+            format.write_record(&mut output, &meta, content).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "_offset,level,msg,tags,raw\n",
+                "28,3,\"line 1\nline \"\"2\"\", done\",\"[\"\"a\"\",\"\"b\"\"]\",\n",
+                "128,,plain,,\n",
+                "228,,,,\"not, json\"\n",
+                "328,-1,\"'=HYPERLINK(\"\"http://x\"\")\",,\n",
+                "428,,,,'@SUM(A1)\n",
+                "528,,,,'-2+3\n",
+                "628,+1,'\tcmd,,\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_header_only() {
+        let mut format = CsvFormat::default();
+        let mut output = Vec::new();
+        format.write_header(&mut output).unwrap();
+        format.write_header(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "_file,_offset,timestamp,level,userId,namespace,msg,raw\n"
+        );
+    }
+}
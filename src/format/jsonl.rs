@@ -0,0 +1,77 @@
+use super::{RecordFormat, RecordMeta};
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+pub const FILE_KEY: &str = "_file";
+pub const INDEX_KEY: &str = "_index";
+pub const OFFSET_KEY: &str = "_offset";
+pub const RAW_KEY: &str = "raw";
+
+/// Builds the JSON object for a record: the metadata keys, followed by the
+/// fields of the record if it is a JSON object, or by `raw` holding the
+/// content otherwise. Metadata keys take precedence over record fields.
+pub fn json_record(meta: &RecordMeta, content: &str) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert(FILE_KEY.to_string(), meta.file.into());
+    object.insert(INDEX_KEY.to_string(), meta.index.into());
+    object.insert(OFFSET_KEY.to_string(), meta.offset.into());
+
+    match serde_json::from_str::<Value>(content) {
+        Ok(Value::Object(fields)) => {
+            for (key, value) in fields {
+                object.entry(key).or_insert(value);
+            }
+        }
+        _ => {
+            object.insert(RAW_KEY.to_string(), content.into());
+        }
+    }
+
+    object
+}
+
+/// JSON Lines, one object per record as built by [`json_record`].
+pub struct JsonlFormat;
+
+impl RecordFormat for JsonlFormat {
+    fn write_record(
+        &mut self,
+        output: &mut dyn Write,
+        meta: &RecordMeta,
+        content: &str,
+    ) -> io::Result<()> {
+        serde_json::to_writer(&mut *output, &json_record(meta, content))?;
+        output.write_all(b"\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_record;
+    use crate::format::RecordMeta;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_record() {
+        let meta = RecordMeta {
+            file: "a.glog",
+            index: 2,
+            offset: 136,
+        };
+
+        let object = json_record(&meta, r#"{"msg":"save:1","level":"3","_file":"x"}"#);
+        assert_eq!(
+            serde_json::to_string(&object).unwrap(),
+            r#"{"_file":"a.glog","_index":2,"_offset":136,"msg":"save:1","level":"3"}"#
+        );
+
+        for content in ["plain text", "[1, 2]", r#"{"msg":"#] {
+            assert_eq!(
+                json_record(&meta, content)["raw"],
+                json!(content),
+                "{}",
+                content
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::{jsonl::json_record, value_text, RecordFormat, RecordMeta};
+use serde_json::Value;
+use std::io::{self, Write};
+
+/// Replaces the characters a logfmt key cannot hold with `_`.
+fn write_key(output: &mut dyn Write, key: &str) -> io::Result<()> {
+    if key.is_empty() {
+        return output.write_all(b"_");
+    }
+    for c in key.chars() {
+        let c = if c <= ' ' || c == '=' || c == '"' {
+            '_'
+        } else {
+            c
+        };
+        write!(output, "{}", c)?;
+    }
+    Ok(())
+}
+
+/// Quotes a value if it is empty or holds spaces, `=`, quotes or control
+/// characters. Line breaks are escaped, so every record stays on one line.
+fn write_value(output: &mut dyn Write, value: &str) -> io::Result<()> {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\');
+    if !needs_quotes {
+        return output.write_all(value.as_bytes());
+    }
+
+    output.write_all(b"\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => output.write_all(b"\\\"")?,
+            '\\' => output.write_all(b"\\\\")?,
+            '\n' => output.write_all(b"\\n")?,
+            '\r' => output.write_all(b"\\r")?,
+            '\t' => output.write_all(b"\\t")?,
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32)?,
+            c => write!(output, "{}", c)?,
+        }
+    }
+    output.write_all(b"\"")
+}
+
+/// logfmt, one line of `key=value` pairs per record with the fields of its
+/// [`json_record`] object. Null fields have an empty value.
+pub struct LogfmtFormat;
+
+impl RecordFormat for LogfmtFormat {
+    fn write_record(
+        &mut self,
+        output: &mut dyn Write,
+        meta: &RecordMeta,
+        content: &str,
+    ) -> io::Result<()> {
+        for (i, (key, value)) in json_record(meta, content).iter().enumerate() {
+            if i > 0 {
+                output.write_all(b" ")?;
+            }
+            write_key(output, key)?;
+            output.write_all(b"=")?;
+            if *value != Value::Null {
+                write_value(output, &value_text(value))?;
+            }
+        }
+        output.write_all(b"\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogfmtFormat;
+    use crate::format::{RecordFormat, RecordMeta};
+
+    #[test]
+    fn test_logfmt_format() {
+        let meta = RecordMeta {
+            file: "logs/app 1.glog",
+            index: 0,
+            offset: 28,
+        };
+        let mut output = Vec::new();
+        LogfmtFormat
+            .write_record(
+                &mut output,
+                &meta,
+                r#"{"msg":"saved \"a\"\n\tat C:\\x","level":"3","ok":true,"user id":"","ctx":{"k":1},"none":null}"#,
+            )
+            .unwrap();
+        LogfmtFormat
+            .write_record(&mut output, &meta, "plain=text")
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"_file="logs/app 1.glog" _index=0 _offset=28 msg="saved \"a\"\n\tat C:\\x" level=3 ok=true user_id="" ctx="{\"k\":1}" none="#,
+                "\n",
+                r#"_file="logs/app 1.glog" _index=0 _offset=28 raw="plain=text""#,
+                "\n",
+            )
+        );
+    }
+}
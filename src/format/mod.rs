@@ -0,0 +1,60 @@
+pub mod csv;
+pub mod jsonl;
+pub mod logfmt;
+
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+/// Where a decoded record came from.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordMeta<'a> {
+    pub file: &'a str,
+    /// Position of the record in its file, starting at 0.
+    pub index: usize,
+    /// Byte offset of the record in its file.
+    pub offset: i64,
+}
+
+/// Output format for decoded records.
+pub trait RecordFormat {
+    /// Writes what comes before the records, such as a header row, so that
+    /// an output without records is still valid. Does nothing when called
+    /// again or after a record.
+    fn write_header(&mut self, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        output: &mut dyn Write,
+        meta: &RecordMeta,
+        content: &str,
+    ) -> io::Result<()>;
+}
+
+/// The record content as is, one record per line.
+pub struct TextFormat;
+
+impl RecordFormat for TextFormat {
+    fn write_record(
+        &mut self,
+        output: &mut dyn Write,
+        _meta: &RecordMeta,
+        content: &str,
+    ) -> io::Result<()> {
+        writeln!(output, "{}", content)
+    }
+}
+
+/// A field value as plain text: strings without quotes, null as empty, and
+/// arrays and objects as compact JSON.
+fn value_text(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::Null => Cow::Borrowed(""),
+        Value::String(s) => Cow::Borrowed(s),
+        value => Cow::Owned(value.to_string()),
+    }
+}
@@ -0,0 +1,881 @@
+use super::primitive::{CompressMode, EncryptMode, SINGLE_LOG_CONTENT_MAX_LENGTH};
+use crate::cipher::{
+    aes_cfb_ecdh::{Cipher, KeyDerivation},
+    error::CipherError,
+};
+use anyhow::Result;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
+use thiserror::Error;
+use zstd::stream::raw::{Decoder, Encoder, InBuffer, Operation, OutBuffer};
+
+/// Compresses record bodies for one compress mode. An instance lives as long
+/// as the writer, so it may keep state across records.
+///
+/// Output that does not fit in a record fails with [`PayloadLengthError`],
+/// and the output for the next record must still decode after the records
+/// written before the failed one.
+pub trait Compressor: Send {
+    fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Inverse of [`Compressor`]. Records are fed in file order and the output is
+/// appended to `output`; returns the new length of `output`.
+pub trait Decompressor: Send {
+    fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize>;
+}
+
+/// Inputs of a [`RecordCipher`] for a single record.
+pub struct CipherContext<'a> {
+    /// Public key of the other party: the server key when encrypting, the
+    /// record's client key when decrypting.
+    pub peer_pub_key: &'a [u8],
+    pub iv: &'a [u8],
+    pub proto_name: &'a [u8],
+}
+
+/// Encrypts record bodies for one encrypt mode. Every encrypted record
+/// carries an IV and the writer's public key ahead of its body.
+pub trait RecordCipher: Send + Sync {
+    fn encrypt(
+        &self,
+        cipher: &Cipher,
+        context: &CipherContext,
+        buffer: &mut [u8],
+    ) -> Result<(), CipherError>;
+
+    fn decrypt(
+        &self,
+        cipher: &Cipher,
+        context: &CipherContext,
+        buffer: &mut [u8],
+    ) -> Result<(), CipherError>;
+}
+
+/// A record payload that readers would reject: empty, or longer than
+/// [`SINGLE_LOG_CONTENT_MAX_LENGTH`] after compression.
+#[derive(Debug, Error)]
+#[error(
+    "encoded record payload of {0} bytes is outside 1..={}",
+    SINGLE_LOG_CONTENT_MAX_LENGTH
+)]
+pub struct PayloadLengthError(pub usize);
+
+pub(crate) fn check_payload_length(payload: &[u8]) -> Result<(), PayloadLengthError> {
+    if payload.is_empty() || payload.len() > SINGLE_LOG_CONTENT_MAX_LENGTH {
+        return Err(PayloadLengthError(payload.len()));
+    }
+    Ok(())
+}
+
+type CompressorFactory = Arc<dyn Fn() -> Result<Box<dyn Compressor>> + Send + Sync>;
+type DecompressorFactory = Arc<dyn Fn() -> Result<Box<dyn Decompressor>> + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("compress mode {0} does not fit in a nibble")]
+    InvalidCompressMode(u8),
+
+    #[error("encrypt mode {0} does not fit in a nibble or means no encryption")]
+    InvalidEncryptMode(u8),
+
+    #[error("compress mode {0} is already registered")]
+    DuplicateCompressMode(u8),
+
+    #[error("compress mode {0} is a built-in mode")]
+    BuiltinCompressMode(u8),
+
+    #[error("encrypt mode {0} is already registered")]
+    DuplicateEncryptMode(u8),
+}
+
+/// Compressors and ciphers by mode nibble.
+///
+/// The default registry knows every built-in mode. Custom schemes can be
+/// registered under free [`CompressMode::Other`] / [`EncryptMode::Other`]
+/// ids; readers and writers must agree on the registry.
+#[derive(Clone)]
+pub struct CodecRegistry {
+    compressors: HashMap<u8, (CompressorFactory, DecompressorFactory)>,
+    ciphers: HashMap<u8, Arc<dyn RecordCipher>>,
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            compressors: HashMap::new(),
+            ciphers: HashMap::new(),
+        };
+
+        registry.compressors.insert(
+            CompressMode::None.into(),
+            (
+                Arc::new(|| Ok(Box::new(NoCompression))),
+                Arc::new(|| Ok(Box::new(NoCompression))),
+            ),
+        );
+        registry.compressors.insert(
+            CompressMode::Zlib.into(),
+            (
+                Arc::new(|| Ok(Box::<ZlibCompressor>::default())),
+                Arc::new(|| Ok(Box::<ZlibDecompressor>::default())),
+            ),
+        );
+        registry.compressors.insert(
+            CompressMode::Lz4.into(),
+            (
+                Arc::new(|| Ok(Box::<Lz4Compressor>::default())),
+                Arc::new(|| Ok(Box::<Lz4Decompressor>::default())),
+            ),
+        );
+        registry = registry.with_zstd(zstd::DEFAULT_COMPRESSION_LEVEL, &[]);
+
+        registry.ciphers.insert(
+            EncryptMode::Aes.into(),
+            Arc::new(AesCfbCipher { hkdf: false }),
+        );
+        registry.ciphers.insert(
+            EncryptMode::AesHkdf.into(),
+            Arc::new(AesCfbCipher { hkdf: true }),
+        );
+        registry
+    }
+}
+
+impl CodecRegistry {
+    pub fn register_compression<C, D>(
+        &mut self,
+        mode: CompressMode,
+        compressor: C,
+        decompressor: D,
+    ) -> Result<(), RegistryError>
+    where
+        C: Fn() -> Result<Box<dyn Compressor>> + Send + Sync + 'static,
+        D: Fn() -> Result<Box<dyn Decompressor>> + Send + Sync + 'static,
+    {
+        let id = u8::from(mode);
+        if !(1..=0x0F).contains(&id) {
+            return Err(RegistryError::InvalidCompressMode(id));
+        }
+        // `Other(5)` is the nibble of `ZlibReset`, which readers hand to zlib
+        if !matches!(CompressMode::from(id), CompressMode::Other(_)) {
+            return Err(RegistryError::BuiltinCompressMode(id));
+        }
+
+        match self.compressors.entry(id) {
+            Entry::Occupied(_) => Err(RegistryError::DuplicateCompressMode(id)),
+            Entry::Vacant(entry) => {
+                entry.insert((Arc::new(compressor), Arc::new(decompressor)));
+                Ok(())
+            }
+        }
+    }
+
+    pub fn register_cipher(
+        &mut self,
+        mode: EncryptMode,
+        cipher: impl RecordCipher + 'static,
+    ) -> Result<(), RegistryError> {
+        let id = u8::from(mode);
+        if !(2..=0x0F).contains(&id) {
+            return Err(RegistryError::InvalidEncryptMode(id));
+        }
+
+        match self.ciphers.entry(id) {
+            Entry::Occupied(_) => Err(RegistryError::DuplicateEncryptMode(id)),
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(cipher));
+                Ok(())
+            }
+        }
+    }
+
+    /// Configures zstd records with a compression level and an optional
+    /// trained dictionary, e.g. one built with `zstd::dict::from_samples`.
+    /// Readers need the same dictionary. Replaces the previous zstd settings.
+    pub fn with_zstd(mut self, level: i32, dictionary: &[u8]) -> Self {
+        let dictionary: Arc<[u8]> = dictionary.into();
+        let decoder_dictionary = dictionary.clone();
+
+        let compressor: CompressorFactory =
+            Arc::new(move || Ok(Box::new(ZstdCompressor::new(level, &dictionary)?)));
+        let decompressor: DecompressorFactory =
+            Arc::new(move || Ok(Box::new(ZstdDecompressor::new(&decoder_dictionary)?)));
+        self.compressors
+            .insert(CompressMode::Zstd.into(), (compressor, decompressor));
+        self
+    }
+
+    pub fn supports_compression(&self, mode: CompressMode) -> bool {
+        self.compressors.contains_key(&mode.codec().into())
+    }
+
+    pub fn supports_encryption(&self, mode: EncryptMode) -> bool {
+        mode == EncryptMode::None || self.ciphers.contains_key(&mode.into())
+    }
+
+    pub fn new_compressor(&self, mode: CompressMode) -> Result<Box<dyn Compressor>> {
+        match self.compressors.get(&mode.codec().into()) {
+            Some((factory, _)) => factory(),
+            None => Err(anyhow::anyhow!("unsupported compress mode: {:?}", mode)),
+        }
+    }
+
+    pub fn new_decompressor(&self, mode: CompressMode) -> Result<Box<dyn Decompressor>> {
+        match self.compressors.get(&mode.codec().into()) {
+            Some((_, factory)) => factory(),
+            None => Err(anyhow::anyhow!("unsupported compress mode: {:?}", mode)),
+        }
+    }
+
+    pub fn cipher(&self, mode: EncryptMode) -> Option<&dyn RecordCipher> {
+        self.ciphers.get(&mode.into()).map(Arc::as_ref)
+    }
+}
+
+pub struct NoCompression;
+
+impl Compressor for NoCompression {
+    fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        Ok(input.to_vec())
+    }
+}
+
+impl Decompressor for NoCompression {
+    fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+        output.extend_from_slice(input);
+        Ok(output.len())
+    }
+}
+
+/// Raw deflate on one stream shared by all records, sync flushed per record.
+pub struct ZlibCompressor {
+    compressor: Compress,
+}
+
+impl Default for ZlibCompressor {
+    fn default() -> Self {
+        Self {
+            compressor: Compress::new_with_window_bits(Compression::default(), false, 15),
+        }
+    }
+}
+
+impl Compressor for ZlibCompressor {
+    fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+        let start_in = self.compressor.total_in();
+        loop {
+            let consumed = (self.compressor.total_in() - start_in) as usize;
+            self.compressor
+                .compress_vec(&input[consumed..], &mut output, FlushCompress::Sync)?;
+
+            // the flush is complete once it stops short of filling the output
+            let consumed = (self.compressor.total_in() - start_in) as usize;
+            if consumed == input.len() && output.len() < output.capacity() {
+                break;
+            }
+            output.reserve(SINGLE_LOG_CONTENT_MAX_LENGTH);
+        }
+
+        if let Err(e) = check_payload_length(&output) {
+            // a fresh stream continues the one readers have seen so far
+            self.compressor.reset();
+            return Err(e.into());
+        }
+        Ok(output)
+    }
+}
+
+/// Empty stored block that ends every sync flushed deflate chunk.
+const ZLIB_SYNC_FLUSH_TRAILER: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+
+/// Default cap on the inflated size of a single record.
+pub const MAX_INFLATED_RECORD_LENGTH: usize = 64 * SINGLE_LOG_CONTENT_MAX_LENGTH;
+
+#[derive(Debug, Error)]
+pub enum InflateError {
+    #[error("inflated record exceeds {limit} bytes")]
+    OutputTooLarge { limit: usize },
+
+    #[error("record ends in the middle of a compressed block")]
+    Truncated,
+
+    #[error("zlib stream stopped making progress")]
+    Stalled,
+
+    #[error("{0} bytes after the end of the zlib stream")]
+    TrailingData(usize),
+
+    #[error("corrupt zlib stream")]
+    Corrupt(#[from] flate2::DecompressError),
+}
+
+pub struct ZlibDecompressor {
+    decompressor: Decompress, // use flate2::Decompress as mutable decompressor
+    max_output: usize,
+}
+
+impl Default for ZlibDecompressor {
+    fn default() -> Self {
+        Self {
+            decompressor: Decompress::new_with_window_bits(false, 15),
+            max_output: MAX_INFLATED_RECORD_LENGTH,
+        }
+    }
+}
+
+impl ZlibDecompressor {
+    /// Fails records that inflate to more than `max_output` bytes instead of
+    /// [`MAX_INFLATED_RECORD_LENGTH`].
+    pub fn with_max_output(mut self, max_output: usize) -> Self {
+        self.max_output = max_output;
+        self
+    }
+
+    fn inflate(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), InflateError> {
+        let start_in = self.decompressor.total_in();
+        let start_len = output.len();
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(SINGLE_LOG_CONTENT_MAX_LENGTH);
+            }
+
+            let consumed = (self.decompressor.total_in() - start_in) as usize;
+            let before_len = output.len();
+            let status = self.decompressor.decompress_vec(
+                &input[consumed..],
+                output,
+                FlushDecompress::Sync,
+            )?;
+
+            if output.len() - start_len > self.max_output {
+                return Err(InflateError::OutputTooLarge {
+                    limit: self.max_output,
+                });
+            }
+
+            let consumed_now = (self.decompressor.total_in() - start_in) as usize;
+            let output_full = output.len() == output.capacity();
+
+            match status {
+                Status::StreamEnd => {
+                    // the writer finished its stream; the next record, if any,
+                    // starts a new one
+                    self.decompressor.reset(false);
+                    if consumed_now < input.len() {
+                        return Err(InflateError::TrailingData(input.len() - consumed_now));
+                    }
+                    return Ok(());
+                }
+                Status::Ok | Status::BufError => {
+                    if consumed_now == input.len() && !output_full {
+                        // every record is sync flushed by the writer
+                        if !input.ends_with(&ZLIB_SYNC_FLUSH_TRAILER) {
+                            return Err(InflateError::Truncated);
+                        }
+                        return Ok(());
+                    }
+                    if consumed_now == consumed && output.len() == before_len && !output_full {
+                        return Err(InflateError::Stalled);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Decompressor for ZlibDecompressor {
+    /// Inflates one record, growing `output` as needed. On error the stream
+    /// is reset, so later records only decode from the next record where the
+    /// writer reset its stream as well (see
+    /// `LogBufWriterV4::with_zlib_reset_interval`).
+    fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+        if let Err(e) = self.inflate(input, output) {
+            self.decompressor.reset(false);
+            return Err(e.into());
+        }
+        Ok(output.len())
+    }
+}
+
+/// One zstd stream shared by all records and flushed after each one, so a
+/// record can be decoded as soon as it is read, like zlib's sync flush.
+pub struct ZstdCompressor {
+    encoder: Encoder<'static>,
+    /// Set after a rejected record: the next record ends the frame readers
+    /// are in before starting the new one.
+    end_frame: bool,
+}
+
+/// Empty raw block flagged as the last one of its frame.
+const ZSTD_LAST_EMPTY_BLOCK: [u8; 3] = [0x01, 0x00, 0x00];
+
+/// Size of a zstd block header, which is what the decoder asks for next when
+/// a record ends on a block boundary.
+const ZSTD_BLOCK_HEADER_LENGTH: usize = 3;
+
+impl ZstdCompressor {
+    pub fn new(level: i32, dictionary: &[u8]) -> Result<Self> {
+        Ok(Self {
+            encoder: Encoder::with_dictionary(level, dictionary)?,
+            end_frame: false,
+        })
+    }
+
+    fn encode(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let mut in_buffer = InBuffer::around(input);
+        while in_buffer.pos() < input.len() {
+            if output.len() == output.capacity() {
+                output.reserve(SINGLE_LOG_CONTENT_MAX_LENGTH);
+            }
+            let pos = output.len();
+            self.encoder
+                .run(&mut in_buffer, &mut OutBuffer::around_pos(output, pos))?;
+        }
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(SINGLE_LOG_CONTENT_MAX_LENGTH);
+            }
+            let pos = output.len();
+            if self
+                .encoder
+                .flush(&mut OutBuffer::around_pos(output, pos))?
+                == 0
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Compressor for ZstdCompressor {
+    /// Input that does not fit in a record is compressed in full before being
+    /// rejected. The frame it went into is then abandoned, and the next record
+    /// closes it for readers and starts a new one.
+    fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+        if self.end_frame {
+            output.extend_from_slice(&ZSTD_LAST_EMPTY_BLOCK);
+        }
+
+        let checked = self
+            .encode(input, &mut output)
+            .and_then(|()| Ok(check_payload_length(&output)?));
+        if let Err(e) = checked {
+            self.encoder.reinit()?;
+            self.end_frame = true;
+            return Err(e);
+        }
+        self.end_frame = false;
+        Ok(output)
+    }
+}
+
+pub struct ZstdDecompressor {
+    decoder: Decoder<'static>,
+}
+
+impl ZstdDecompressor {
+    pub fn new(dictionary: &[u8]) -> Result<Self> {
+        Ok(Self {
+            decoder: Decoder::with_dictionary(dictionary)?,
+        })
+    }
+
+    fn inflate(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let start_len = output.len();
+        let mut in_buffer = InBuffer::around(input);
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(SINGLE_LOG_CONTENT_MAX_LENGTH);
+            }
+
+            let pos = output.len();
+            let mut out = OutBuffer::around_pos(output, pos);
+            let hint = self.decoder.run(&mut in_buffer, &mut out)?;
+            let output_full = out.pos() == out.capacity();
+
+            if output.len() - start_len > MAX_INFLATED_RECORD_LENGTH {
+                return Err(InflateError::OutputTooLarge {
+                    limit: MAX_INFLATED_RECORD_LENGTH,
+                }
+                .into());
+            }
+
+            if in_buffer.pos() == input.len() && !output_full {
+                // every record is flushed by the writer, so it ends with a
+                // frame or right before the next block header
+                if hint != 0 && hint != ZSTD_BLOCK_HEADER_LENGTH {
+                    return Err(InflateError::Truncated.into());
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Decompressor for ZstdDecompressor {
+    /// Fails records that inflate to more than [`MAX_INFLATED_RECORD_LENGTH`]
+    /// bytes or stop in the middle of a block, and resets the stream, like
+    /// the zlib decompressor.
+    fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+        if let Err(e) = self.inflate(input, output) {
+            self.decoder.reinit()?;
+            return Err(e);
+        }
+        Ok(output.len())
+    }
+}
+
+/// Plaintext of previous lz4 records kept as dictionary for the next one.
+/// Larger windows barely improve the ratio on typical records but make every
+/// block pay for hashing the whole dictionary.
+pub const LZ4_HISTORY_LENGTH: usize = 1024;
+
+fn push_lz4_history(history: &mut Vec<u8>, bytes: &[u8]) {
+    history.extend_from_slice(bytes);
+    if history.len() > LZ4_HISTORY_LENGTH {
+        history.drain(..history.len() - LZ4_HISTORY_LENGTH);
+    }
+}
+
+/// Size-prefixed lz4 blocks, each using the previous records' plaintext as
+/// dictionary.
+#[derive(Default)]
+pub struct Lz4Compressor {
+    history: Vec<u8>,
+}
+
+impl Compressor for Lz4Compressor {
+    fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let output = lz4_flex::block::compress_prepend_size_with_dict(input, &self.history);
+        check_payload_length(&output)?;
+        push_lz4_history(&mut self.history, input);
+        Ok(output)
+    }
+}
+
+#[derive(Default)]
+pub struct Lz4Decompressor {
+    history: Vec<u8>,
+}
+
+impl Decompressor for Lz4Decompressor {
+    /// Fails before allocating anything if the size prefix is larger than
+    /// [`MAX_INFLATED_RECORD_LENGTH`].
+    fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+        if let Some(prefix) = input.get(..4) {
+            let size = u32::from_le_bytes(prefix.try_into()?) as usize;
+            if size > MAX_INFLATED_RECORD_LENGTH {
+                return Err(InflateError::OutputTooLarge {
+                    limit: MAX_INFLATED_RECORD_LENGTH,
+                }
+                .into());
+            }
+        }
+        let block = lz4_flex::block::decompress_size_prepended_with_dict(input, &self.history)?;
+        push_lz4_history(&mut self.history, &block);
+        output.extend_from_slice(&block);
+        Ok(output.len())
+    }
+}
+
+/// AES-128-CFB keyed by ECDH, with the legacy truncated key or HKDF-SHA256.
+pub struct AesCfbCipher {
+    pub hkdf: bool,
+}
+
+impl AesCfbCipher {
+    fn key_derivation<'a>(&self, context: &CipherContext<'a>) -> KeyDerivation<'a> {
+        if self.hkdf {
+            KeyDerivation::HkdfSha256 {
+                salt: context.iv,
+                info: context.proto_name,
+            }
+        } else {
+            KeyDerivation::Truncate
+        }
+    }
+}
+
+impl RecordCipher for AesCfbCipher {
+    fn encrypt(
+        &self,
+        cipher: &Cipher,
+        context: &CipherContext,
+        buffer: &mut [u8],
+    ) -> Result<(), CipherError> {
+        cipher.encrypt_inplace_with(
+            &self.key_derivation(context),
+            context.peer_pub_key,
+            context.iv,
+            buffer,
+        )
+    }
+
+    fn decrypt(
+        &self,
+        cipher: &Cipher,
+        context: &CipherContext,
+        buffer: &mut [u8],
+    ) -> Result<(), CipherError> {
+        cipher.decrypt_inplace_with(
+            &self.key_derivation(context),
+            context.peer_pub_key,
+            context.iv,
+            buffer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AesCfbCipher, CodecRegistry, Compressor, Decompressor, InflateError, Lz4Decompressor,
+        PayloadLengthError, RegistryError, ZlibCompressor, ZlibDecompressor, ZstdCompressor,
+        ZstdDecompressor, MAX_INFLATED_RECORD_LENGTH,
+    };
+    use crate::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            log_reader::{LogBufReadError, LogBufReaderV4},
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode, RecordMode},
+        },
+    };
+    use anyhow::Result;
+
+    /// Toy scheme for the registry test: reverses the record bytes.
+    struct Reverse;
+
+    impl Compressor for Reverse {
+        fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+            Ok(input.iter().rev().copied().collect())
+        }
+    }
+
+    impl Decompressor for Reverse {
+        fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize> {
+            output.extend(input.iter().rev());
+            Ok(output.len())
+        }
+    }
+
+    #[test]
+    fn test_record_mode() {
+        for byte in [0x11, 0x23, 0x42, 0xF1, 0x1F] {
+            let mode = RecordMode::try_from(byte).unwrap();
+            assert_eq!(u8::from(mode), byte);
+        }
+        assert_eq!(
+            RecordMode::try_from(0x32).unwrap(),
+            RecordMode::new(CompressMode::Zstd, EncryptMode::Aes)
+        );
+        assert!(RecordMode::try_from(0x01).is_err());
+        assert!(RecordMode::try_from(0x10).is_err());
+    }
+
+    #[test]
+    fn test_custom_compression() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let mut registry = CodecRegistry::default();
+        registry.register_compression(
+            CompressMode::Other(9),
+            || Ok(Box::new(Reverse)),
+            || Ok(Box::new(Reverse)),
+        )?;
+        assert!(matches!(
+            registry.register_compression(
+                CompressMode::Other(9),
+                || Ok(Box::new(Reverse)),
+                || Ok(Box::new(Reverse)),
+            ),
+            Err(RegistryError::DuplicateCompressMode(9))
+        ));
+        assert!(matches!(
+            registry.register_compression(
+                CompressMode::Other(16),
+                || Ok(Box::new(Reverse)),
+                || Ok(Box::new(Reverse)),
+            ),
+            Err(RegistryError::InvalidCompressMode(16))
+        ));
+        for mode in [CompressMode::Zlib, CompressMode::Other(5)] {
+            assert!(matches!(
+                registry.register_compression(
+                    mode,
+                    || Ok(Box::new(Reverse)),
+                    || Ok(Box::new(Reverse)),
+                ),
+                Err(RegistryError::BuiltinCompressMode(_))
+            ));
+        }
+        assert!(matches!(
+            registry.register_cipher(EncryptMode::Aes, AesCfbCipher { hkdf: false }),
+            Err(RegistryError::DuplicateEncryptMode(2))
+        ));
+        assert!(matches!(
+            registry.register_cipher(EncryptMode::None, AesCfbCipher { hkdf: false }),
+            Err(RegistryError::InvalidEncryptMode(1))
+        ));
+
+        let mut file = Vec::new();
+        let mut writer =
+            LogBufWriterV4::new(&mut file, &client_cipher).with_registry(registry.clone());
+        writer.write_head()?;
+        writer.write_single_log(
+            (CompressMode::Other(9), EncryptMode::Aes),
+            &server_key_pair.public_key,
+            "hello world",
+        )?;
+        drop(writer);
+
+        let mut logs = Vec::new();
+        LogBufReaderV4::new(file.as_slice(), &server_cipher)
+            .with_registry(registry)
+            .read(|content| logs.push(content.to_string()))?;
+        assert_eq!(logs, ["hello world"]);
+
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read_header()?;
+        assert!(matches!(
+            reader.read_record(),
+            Err(LogBufReadError::InvalidCompressMode(9))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zlib_decompress() -> Result<()> {
+        let large: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut compressor = ZlibCompressor::default();
+        let records = [
+            compressor.compress(b"first")?,
+            compressor.compress(&large)?,
+            compressor.compress(b"last")?,
+        ];
+
+        let mut decompressor = ZlibDecompressor::default();
+        let mut output = Vec::new();
+        for (record, expected) in records.iter().zip([&b"first"[..], &large, b"last"]) {
+            output.clear();
+            decompressor.decompress(record, &mut output)?;
+            assert_eq!(output, expected);
+        }
+
+        // incompressible input is compressed in full before being rejected
+        let random: Vec<u8> = (0..100_000).map(|_| rand::random()).collect();
+        let error = ZlibCompressor::default().compress(&random).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(PayloadLengthError(length)) if *length > random.len()
+        ));
+
+        let mut decompressor = ZlibDecompressor::default().with_max_output(1000);
+        decompressor.decompress(&records[0], &mut Vec::new())?;
+        let error = decompressor
+            .decompress(&records[1], &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InflateError::OutputTooLarge { limit: 1000 })
+        ));
+
+        let record = ZlibCompressor::default().compress(&large)?;
+        let truncated = &record[..record.len() / 2];
+        let error = ZlibDecompressor::default()
+            .decompress(truncated, &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InflateError::Truncated)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lz4_size_prefix() {
+        // claims a block of 2 GiB
+        let record = [0x00, 0x00, 0x00, 0x80, 0x00];
+        let error = Lz4Decompressor::default()
+            .decompress(&record, &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InflateError::OutputTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_zstd_output_cap() -> Result<()> {
+        let mut compressor = ZstdCompressor::new(3, &[])?;
+        let mut decompressor = ZstdDecompressor::new(&[])?;
+        let mut output = Vec::new();
+        decompressor.decompress(&compressor.compress(b"first")?, &mut output)?;
+        assert_eq!(output, b"first");
+
+        // a single frame that inflates past the cap
+        let bomb = zstd::bulk::compress(&vec![0; 2 * MAX_INFLATED_RECORD_LENGTH], 3)?;
+        let error = ZstdDecompressor::new(&[])?
+            .decompress(&bomb, &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InflateError::OutputTooLarge { .. })
+        ));
+
+        Ok(())
+    }
+    #[test]
+    fn test_zstd_decompress() -> Result<()> {
+        // compresses far below its bound
+        let large = "{\"level\":\"info\",\"msg\":\"request served\"}\n".repeat(2500);
+        let random: Vec<u8> = (0..100_000).map(|_| rand::random()).collect();
+
+        let mut compressor = ZstdCompressor::new(3, &[])?;
+        let first = compressor.compress(b"first")?;
+        let large_record = compressor.compress(large.as_bytes())?;
+        let error = compressor.compress(&random).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(PayloadLengthError(length)) if *length > random.len()
+        ));
+        let last = compressor.compress(b"last")?;
+
+        // the rejected record is never written
+        let mut decompressor = ZstdDecompressor::new(&[])?;
+        let mut output = Vec::new();
+        for (record, expected) in
+            [&first, &large_record, &last]
+                .iter()
+                .zip([&b"first"[..], large.as_bytes(), b"last"])
+        {
+            output.clear();
+            decompressor.decompress(record, &mut output)?;
+            assert_eq!(output, expected);
+        }
+
+        let mut decompressor = ZstdDecompressor::new(&[])?;
+        decompressor.decompress(&first, &mut Vec::new())?;
+        let error = decompressor
+            .decompress(&large_record[..large_record.len() - 1], &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InflateError::Truncated)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use super::{
+    codec::CodecRegistry,
+    log_reader::{LogBufReadError, LogBufReaderV4},
+    metrics::DecodeMetrics,
+    primitive::SINGLE_LOG_CONTENT_MAX_LENGTH,
+};
+use crate::{
+    cipher::{aes_cfb_ecdh::Cipher, error::CipherError},
+    format::RecordMeta,
+};
+use std::{
+    fs::{File, Metadata},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use thiserror::Error;
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Raised by [`TailReader`] at the end of a file that was truncated or
+/// replaced, so that the follower starts over with the new contents.
+#[derive(Debug, Error)]
+#[error("file was truncated or replaced")]
+struct FileReplaced;
+
+/// A file reader that waits for more data at the end of the file instead of
+/// returning EOF. Partial records are thus completed once the writer appends
+/// the rest of them.
+struct TailReader {
+    file: File,
+    path: PathBuf,
+    position: u64,
+    poll_interval: Duration,
+}
+
+impl TailReader {
+    fn replaced(&self) -> io::Result<bool> {
+        let metadata = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // removed and not recreated yet, keep reading the old file
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        Ok(metadata.len() < self.position || !same_file(&metadata, &self.file.metadata()?))
+    }
+}
+
+impl Read for TailReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.position += n as u64;
+                return Ok(n);
+            }
+
+            if self.replaced()? {
+                return Err(io::Error::other(FileReplaced));
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    true
+}
+
+fn is_file_replaced(error: &LogBufReadError) -> bool {
+    match error {
+        LogBufReadError::IoError(e) => e.get_ref().is_some_and(|e| e.is::<FileReplaced>()),
+        _ => false,
+    }
+}
+
+/// How the follower gets past a record that failed to decode.
+enum Damage {
+    /// The whole record was read, so the next one should follow.
+    RecordRead,
+    /// Reading stopped inside the record or its sync marker.
+    Resync,
+}
+
+/// `None` for errors that later records would fail with as well.
+fn damage(error: &LogBufReadError) -> Option<Damage> {
+    match error {
+        LogBufReadError::DecryptionError(CipherError::CurveMismatch { .. })
+        | LogBufReadError::WrongKey { .. } => None,
+        // a broken sync marker may still end the record at the right place;
+        // if not, reading the next record fails and resyncs
+        LogBufReadError::DecryptionError(_)
+        | LogBufReadError::DecompressError { .. }
+        | LogBufReadError::InvalidSyncMarker => Some(Damage::RecordRead),
+        LogBufReadError::InvalidLogLength
+        | LogBufReadError::InvalidCompressMode(_)
+        | LogBufReadError::InvalidEncryptMode(_) => Some(Damage::Resync),
+        _ => None,
+    }
+}
+
+/// Decodes a glog file that is still being written, like `tail -f`.
+///
+/// The follower waits at the end of the file, also in the middle of a record,
+/// and continues once more data arrives. When the file is truncated or
+/// replaced by a new one (log rotation), it starts over at the beginning of
+/// the new file with fresh codec state. Records that fail to decode are
+/// skipped, if need be up to the next sync marker.
+pub struct Follower {
+    path: PathBuf,
+    cipher: Cipher,
+    registry: CodecRegistry,
+    poll_interval: Duration,
+    skip_existing: bool,
+    metrics: Option<Arc<dyn DecodeMetrics>>,
+}
+
+impl Follower {
+    pub fn new(path: impl AsRef<Path>, cipher: &Cipher) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            cipher: cipher.clone(),
+            registry: CodecRegistry::default(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            skip_existing: false,
+            metrics: None,
+        }
+    }
+
+    pub fn with_registry(mut self, registry: CodecRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Decodes but does not report the records already in the file when
+    /// following starts. Records of later files are all reported.
+    pub fn with_skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Reports decoded and skipped records to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn DecodeMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Calls `callback` for every record until it returns `false`. Waits for
+    /// the file to appear if it does not exist yet.
+    pub fn follow(
+        &self,
+        mut callback: impl FnMut(&RecordMeta, &str) -> bool,
+    ) -> Result<(), LogBufReadError> {
+        let file_name = self.path.to_string_lossy();
+        let mut skip_until = None;
+
+        loop {
+            let file = self.wait_for_file()?;
+            if self.skip_existing && skip_until.is_none() {
+                skip_until = Some(file.metadata()?.len() as i64);
+            }
+
+            let tail = TailReader {
+                file,
+                path: self.path.clone(),
+                position: 0,
+                poll_interval: self.poll_interval,
+            };
+
+            let mut reader =
+                LogBufReaderV4::new(tail, &self.cipher).with_registry(self.registry.clone());
+            if let Some(metrics) = &self.metrics {
+                reader = reader.with_metrics(metrics.clone());
+            }
+            let result = (|| {
+                reader.read_header()?;
+
+                let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+                let mut index = 0;
+                loop {
+                    buffer.clear();
+                    let result = match reader.read_record() {
+                        Ok(Some(record)) => {
+                            reader.inflate_record(&record, &mut buffer).map(|_| record)
+                        }
+                        Ok(None) => return Ok(()),
+                        Err(e) => Err(e),
+                    };
+                    let record = match result {
+                        Ok(record) => record,
+                        Err(e) => match damage(&e) {
+                            Some(Damage::RecordRead) => {
+                                index += 1;
+                                continue;
+                            }
+                            Some(Damage::Resync) if reader.skip_to_sync_marker()? => continue,
+                            Some(Damage::Resync) => return Ok(()),
+                            None => return Err(e),
+                        },
+                    };
+
+                    if skip_until.is_some_and(|end| reader.position() <= end) {
+                        index += 1;
+                        continue;
+                    }
+
+                    let meta = RecordMeta {
+                        file: &file_name,
+                        index,
+                        offset: record.offset,
+                    };
+                    if !callback(&meta, &String::from_utf8_lossy(&buffer)) {
+                        return Ok(());
+                    }
+                    index += 1;
+                }
+            })();
+
+            match result {
+                Err(e) if is_file_replaced(&e) => {
+                    // only the first file has existing records to skip
+                    skip_until = Some(0);
+                    continue;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn wait_for_file(&self) -> Result<File, LogBufReadError> {
+        loop {
+            match File::open(&self.path) {
+                Ok(file) => return Ok(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => thread::sleep(self.poll_interval),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Follower;
+    use crate::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use anyhow::Result;
+    use std::{fs, io::Write, num::NonZeroU32, sync::mpsc, thread, time::Duration};
+
+    /// Encodes `logs` and returns the file with the end offset of the header
+    /// and of each record.
+    fn encode(cipher: &Cipher, logs: &[&str]) -> Result<(Vec<u8>, Vec<usize>)> {
+        let mut file = Vec::new();
+        let mut ends = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, cipher);
+        writer.write_head()?;
+        ends.push(writer.into_inner().get_ref().len());
+        for log in logs {
+            writer.write_single_log((CompressMode::Zlib, EncryptMode::None), "", log)?;
+            ends.push(writer.into_inner().get_ref().len());
+        }
+        drop(writer);
+        Ok((file, ends))
+    }
+
+    #[test]
+    fn test_follow() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("ATRealTimeLog.glog");
+
+        let cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let (file, ends) = encode(&cipher, &["r0", "r1", "r2"])?;
+        let (rotated, _) = encode(&cipher, &["r3"])?;
+
+        // the first record and half of the second
+        let split = (ends[1] + ends[2]) / 2;
+        fs::write(&path, &file[..split])?;
+
+        let (sender, receiver) = mpsc::channel();
+        let follower = Follower::new(&path, &cipher).with_poll_interval(Duration::from_millis(5));
+        let handle = thread::spawn(move || {
+            follower.follow(|meta, content| {
+                sender.send((meta.index, content.to_string())).unwrap();
+                content != "r3"
+            })
+        });
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(receiver.recv_timeout(timeout)?, (0, "r0".to_string()));
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(&file[split..])?;
+        assert_eq!(receiver.recv_timeout(timeout)?, (1, "r1".to_string()));
+        assert_eq!(receiver.recv_timeout(timeout)?, (2, "r2".to_string()));
+
+        fs::rename(&path, dir.join("ATRealTimeLog.glog.1"))?;
+        fs::write(&path, &rotated)?;
+        assert_eq!(receiver.recv_timeout(timeout)?, (0, "r3".to_string()));
+
+        handle.join().unwrap()?;
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_damaged() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-follow-damaged-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("ATRealTimeLog.glog");
+
+        let cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let mut file = Vec::new();
+        let mut ends = Vec::new();
+        let mut writer =
+            LogBufWriterV4::new(&mut file, &cipher).with_zlib_reset_interval(NonZeroU32::MIN);
+        writer.write_head()?;
+        ends.push(writer.into_inner().get_ref().len());
+        for log in ["r0", "r1", "r2", "r3", "r4", "r5"] {
+            writer.write_single_log((CompressMode::Zlib, EncryptMode::None), "", log)?;
+            ends.push(writer.into_inner().get_ref().len());
+        }
+        drop(writer);
+
+        // the sync marker of r1 and the mode byte of r3
+        file[ends[2] - 1] ^= 0xFF;
+        file[ends[3]] = 0;
+        fs::write(&path, &file)?;
+
+        let mut logs = Vec::new();
+        Follower::new(&path, &cipher)
+            .with_poll_interval(Duration::from_millis(5))
+            .follow(|_, content| {
+                logs.push(content.to_string());
+                content != "r5"
+            })?;
+        assert_eq!(logs, ["r0", "r2", "r4", "r5"]);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use flate2::read::GzDecoder;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+/// Separates the path of an archive from the path of an entry in it, as in
+/// `logs.zip!/app.glog`.
+pub const ARCHIVE_SEPARATOR: &str = "!/";
+
+#[derive(Debug, Error)]
+pub enum InputError {
+    #[error("io error")]
+    IoError(#[from] io::Error),
+
+    #[error("invalid pattern")]
+    Pattern(#[from] glob::PatternError),
+
+    #[error("failed to read zip archive")]
+    Zip(#[from] ZipError),
+
+    #[error("failed to read tar.gz archive")]
+    Tar(#[source] io::Error),
+
+    #[error("no files match {0}")]
+    NoMatch(String),
+}
+
+fn input_error<E: From<InputError>>(error: impl Into<InputError>) -> E {
+    E::from(error.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Glog,
+    Zip,
+    TarGz,
+    Other,
+}
+
+fn kind(name: &str) -> Kind {
+    let name = name.to_ascii_lowercase();
+    if name.ends_with(".glog") {
+        Kind::Glog
+    } else if name.ends_with(".zip") {
+        Kind::Zip
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Kind::TarGz
+    } else {
+        Kind::Other
+    }
+}
+
+/// Calls `on_file` with the name and contents of every glog file in
+/// `input`, one at a time and without reading them into memory first.
+///
+/// `input` may be:
+/// - a directory, searched recursively for `.glog` files and archives, in
+///   path order; symbolic links to directories inside it are skipped, so
+///   that a link cycle cannot make the search endless
+/// - a `.zip`, `.tar.gz` or `.tgz` archive, whose `.glog` entries are named
+///   `<archive>!/<entry>`
+/// - a glob pattern such as `logs/*.glog`, whose matches are read like the
+///   paths above
+/// - any other file, read as a glog file whatever its name
+pub fn for_each_glog<E: From<InputError>>(
+    input: &Path,
+    on_file: &mut dyn FnMut(&str, &mut dyn Read) -> Result<(), E>,
+) -> Result<(), E> {
+    let pattern = input.to_string_lossy();
+    if input.exists() || !pattern.contains(['*', '?', '[']) {
+        return visit_path(input, on_file);
+    }
+
+    let paths = glob::glob(&pattern)
+        .map_err(input_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| input_error(io::Error::from(e)))?;
+    if paths.is_empty() {
+        return Err(input_error(InputError::NoMatch(pattern.into_owned())));
+    }
+    for path in paths {
+        visit_path(&path, on_file)?;
+    }
+    Ok(())
+}
+
+fn visit_path<E: From<InputError>>(
+    path: &Path,
+    on_file: &mut dyn FnMut(&str, &mut dyn Read) -> Result<(), E>,
+) -> Result<(), E> {
+    if path.is_dir() {
+        return visit_dir(path, on_file);
+    }
+
+    let name = path.to_string_lossy();
+    match kind(&name) {
+        Kind::Zip => visit_zip(path, on_file),
+        Kind::TarGz => visit_tar_gz(path, on_file),
+        Kind::Glog | Kind::Other => {
+            let file = File::open(path).map_err(input_error)?;
+            on_file(&name, &mut BufReader::new(file))
+        }
+    }
+}
+
+fn visit_dir<E: From<InputError>>(
+    dir: &Path,
+    on_file: &mut dyn FnMut(&str, &mut dyn Read) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut paths = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<PathBuf>>>()
+        })
+        .map_err(input_error)?;
+    paths.sort();
+
+    for path in paths {
+        if path.is_symlink() && path.is_dir() {
+            continue;
+        }
+        if path.is_dir() || kind(&path.to_string_lossy()) != Kind::Other {
+            visit_path(&path, on_file)?;
+        }
+    }
+    Ok(())
+}
+
+fn visit_zip<E: From<InputError>>(
+    path: &Path,
+    on_file: &mut dyn FnMut(&str, &mut dyn Read) -> Result<(), E>,
+) -> Result<(), E> {
+    let file = File::open(path).map_err(input_error)?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(input_error)?;
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).map_err(input_error)?;
+        if !entry.is_file() || kind(entry.name()) != Kind::Glog {
+            continue;
+        }
+
+        let name = format!("{}{}{}", path.display(), ARCHIVE_SEPARATOR, entry.name());
+        on_file(&name, &mut BufReader::new(entry))?;
+    }
+    Ok(())
+}
+
+fn visit_tar_gz<E: From<InputError>>(
+    path: &Path,
+    on_file: &mut dyn FnMut(&str, &mut dyn Read) -> Result<(), E>,
+) -> Result<(), E> {
+    let file = File::open(path).map_err(input_error)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+    let tar_error = |e| input_error(InputError::Tar(e));
+    for entry in archive.entries().map_err(tar_error)? {
+        let entry = entry.map_err(tar_error)?;
+        let entry_path = entry
+            .path()
+            .map_err(tar_error)?
+            .to_string_lossy()
+            .into_owned();
+        if !entry.header().entry_type().is_file() || kind(&entry_path) != Kind::Glog {
+            continue;
+        }
+
+        let name = format!("{}{}{}", path.display(), ARCHIVE_SEPARATOR, entry_path);
+        on_file(&name, &mut BufReader::new(entry))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{for_each_glog, InputError};
+    use anyhow::Result;
+    use flate2::{write::GzEncoder, Compression};
+    use std::{
+        fs::{self, File},
+        io::{ErrorKind, Write},
+        path::Path,
+    };
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    fn collect(input: &Path) -> Result<Vec<(String, String)>, InputError> {
+        let mut files = Vec::new();
+        for_each_glog(input, &mut |name, reader| {
+            let mut content = String::new();
+            reader.read_to_string(&mut content)?;
+            files.push((name.to_string(), content));
+            Ok::<_, InputError>(())
+        })?;
+        Ok(files)
+    }
+
+    #[test]
+    fn test_for_each_glog() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-input-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub"))?;
+        fs::write(dir.join("a.glog"), "a")?;
+        fs::write(dir.join("sub/b.GLOG"), "b")?;
+        fs::write(dir.join("notes.txt"), "skipped")?;
+
+        let mut zip = ZipWriter::new(File::create(dir.join("bundle.zip"))?);
+        for (name, content) in [("logs/c.glog", "c"), ("readme.txt", "skipped")] {
+            zip.start_file(name, SimpleFileOptions::default())?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?;
+
+        let gz = GzEncoder::new(
+            File::create(dir.join("archive.tar.gz"))?,
+            Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "d.glog", "d".as_bytes())?;
+        tar.into_inner()?.finish()?;
+
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let expected = vec![
+            (path("a.glog"), "a".to_string()),
+            (path("archive.tar.gz!/d.glog"), "d".to_string()),
+            (path("bundle.zip!/logs/c.glog"), "c".to_string()),
+            (path("sub/b.GLOG"), "b".to_string()),
+        ];
+        assert_eq!(collect(&dir)?, expected);
+
+        // explicit files are read whatever their name
+        assert_eq!(
+            collect(&dir.join("notes.txt"))?,
+            [(path("notes.txt"), "skipped".to_string())]
+        );
+        assert_eq!(
+            collect(&dir.join("*.glog"))?,
+            [(path("a.glog"), "a".to_string())]
+        );
+        assert!(matches!(
+            collect(&dir.join("*.missing")),
+            Err(InputError::NoMatch(_))
+        ));
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_errors() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-input-errors-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("broken.zip"), "not a zip archive")?;
+        fs::write(dir.join("broken.tar.gz"), "not gzip data")?;
+
+        assert!(matches!(
+            collect(&dir.join("broken.zip")),
+            Err(InputError::Zip(_))
+        ));
+        assert!(matches!(
+            collect(&dir.join("broken.tar.gz")),
+            Err(InputError::Tar(_))
+        ));
+        // a directory fails on its first broken archive
+        assert!(collect(&dir).is_err());
+        match collect(&dir.join("missing.glog")) {
+            Err(InputError::IoError(e)) => assert_eq!(e.kind(), ErrorKind::NotFound),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_loop() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("glog-input-loop-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub"))?;
+        fs::write(dir.join("sub/a.glog"), "a")?;
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop"))?;
+
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        assert_eq!(collect(&dir)?, [(path("sub/a.glog"), "a".to_string())]);
+        // an explicit link is still followed
+        assert_eq!(
+            collect(&dir.join("sub/loop"))?,
+            [(path("sub/loop/sub/a.glog"), "a".to_string())]
+        );
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
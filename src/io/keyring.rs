@@ -0,0 +1,148 @@
+use super::{
+    codec::CodecRegistry,
+    log_reader::{LogBufReadError, LogBufReaderV4},
+    primitive::EncryptMode,
+    primitive::SINGLE_LOG_CONTENT_MAX_LENGTH,
+};
+use crate::cipher::{
+    aes_cfb_ecdh::Cipher,
+    key_file::{parse_private_key, KeyFileError},
+    key_pair::Curve,
+};
+use std::path::Path;
+
+/// Records decoded per key when looking for the key of a file.
+const PROBE_RECORDS: usize = 16;
+
+/// Server private keys, tried in turn on files that may have been written
+/// for any of them.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: Vec<(String, Cipher)>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, cipher: Cipher) {
+        self.keys.push((name.into(), cipher));
+    }
+
+    /// Adds a private key file, or every private key file in a directory.
+    /// Files ending in `.pub` are skipped. Keys are named by file name.
+    pub fn add_path(&mut self, path: &Path, curve: Curve) -> Result<(), KeyFileError> {
+        if path.is_dir() {
+            let mut paths = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.sort();
+            for path in paths {
+                if path.is_file() && path.extension().is_none_or(|extension| extension != "pub") {
+                    self.add_path(&path, curve)?;
+                }
+            }
+            return Ok(());
+        }
+
+        let key_pair = parse_private_key(&std::fs::read_to_string(path)?, curve)?;
+        let cipher = Cipher::new_with_curve(&key_pair.private_key, key_pair.curve())?;
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        self.add(name.to_string_lossy(), cipher);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Finds the key that decodes the first records of `file`, returning its
+    /// name and cipher. A file without encrypted records matches the first
+    /// key.
+    ///
+    /// A wrong key does not fail AES itself, so a key counts as matching when
+    /// the records it decrypts inflate cleanly and are valid UTF-8.
+    pub fn find(&self, file: &[u8], registry: &CodecRegistry) -> Option<(&str, &Cipher)> {
+        self.keys
+            .iter()
+            .find(|(_, cipher)| decodes(file, cipher, registry))
+            .map(|(name, cipher)| (name.as_str(), cipher))
+    }
+}
+
+fn decodes(file: &[u8], cipher: &Cipher, registry: &CodecRegistry) -> bool {
+    let mut reader = LogBufReaderV4::new(file, cipher).with_registry(registry.clone());
+    if reader.read_header().is_err() {
+        return false;
+    }
+
+    let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+    for _ in 0..PROBE_RECORDS {
+        let record = match reader.read_record() {
+            Ok(Some(record)) => record,
+            Err(LogBufReadError::DecryptionError(_)) => return false,
+            // the file may be damaged further on, the key is still right
+            Ok(None) | Err(_) => return true,
+        };
+
+        buffer.clear();
+        let inflated = reader.inflate_record(&record, &mut buffer).is_ok();
+        if record.mode.encrypt != EncryptMode::None
+            && !(inflated && std::str::from_utf8(&buffer).is_ok())
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keyring;
+    use crate::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            codec::CodecRegistry,
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn test_find_key() -> Result<()> {
+        let old_key = KeyPair::random()?;
+        let new_key = KeyPair::random()?;
+
+        let mut keyring = Keyring::new();
+        keyring.add("old", Cipher::new(&old_key.private_key)?);
+        keyring.add("new", Cipher::new(&new_key.private_key)?);
+
+        let registry = CodecRegistry::default();
+        for mode in [
+            (CompressMode::Zlib, EncryptMode::Aes),
+            (CompressMode::None, EncryptMode::AesHkdf),
+        ] {
+            let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+            let mut file = Vec::new();
+            let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+            writer.write_head()?;
+            for i in 0..3 {
+                writer.write_single_log(mode, &new_key.public_key, &format!("log {}", i))?;
+            }
+            drop(writer);
+
+            let (name, _) = keyring.find(&file, &registry).unwrap();
+            assert_eq!(name, "new");
+        }
+
+        assert!(keyring.find(b"not a glog file", &registry).is_none());
+        Ok(())
+    }
+}
@@ -1,13 +1,21 @@
-use super::primitive::{
-    CompressMode, EncryptMode, FileVersion, MAGIC_NUMBER, SINGLE_LOG_CONTENT_MAX_LENGTH,
-    SYNC_MARKER,
+use super::{
+    codec::{CipherContext, CodecRegistry, Decompressor},
+    metrics::{DecodeFailure, DecodeMetrics},
+    primitive::{
+        CompressMode, EncryptMode, FileVersion, RecordMode, HEADER_FLAG_CURVE,
+        HEADER_FLAG_ZLIB_RESET, MAGIC_NUMBER, SINGLE_LOG_CONTENT_MAX_LENGTH, SYNC_MARKER,
+    },
 };
-use crate::cipher::aes_cfb_ecdh::Cipher;
-use anyhow::Result;
+use crate::cipher::{aes_cfb_ecdh::Cipher, error::CipherError, key_pair::Curve};
 use byteorder::{LittleEndian, ReadBytesExt};
-use flate2::{Decompress, FlushDecompress};
 use num_traits::FromPrimitive;
-use std::io::{BufReader, Read};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{BufRead, BufReader, Read},
+    num::NonZeroU32,
+    sync::Arc,
+    time::Instant,
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -24,6 +32,9 @@ pub enum LogBufReadError {
     #[error("invalid version")]
     InvalidVersion,
 
+    #[error("unknown header flags: {0:#04x}")]
+    InvalidHeaderFlags(u8),
+
     #[error("invalid sync marker")]
     InvalidSyncMarker,
 
@@ -31,17 +42,58 @@ pub enum LogBufReadError {
     InvalidLogLength,
 
     #[error("decryption error")]
-    DecryptionError,
+    DecryptionError(#[source] CipherError),
+
+    #[error("decompress error in record at offset {offset}")]
+    DecompressError {
+        offset: i64,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("illegal compress mode: {0}")]
+    InvalidCompressMode(u8),
+
+    #[error("illegal encrypt mode: {0}")]
+    InvalidEncryptMode(u8),
+
+    /// A wrong key garbles every encrypted record, so the first one already
+    /// fails; later failures point at damaged data instead.
+    #[error("the first encrypted record, at offset {offset}, does not decode; wrong key?")]
+    WrongKey {
+        offset: i64,
+        #[source]
+        source: Box<LogBufReadError>,
+    },
+}
 
-    #[error("decompress error")]
-    DecompressError,
+/// A single record as laid out in a V4 file.
+///
+/// `iv` and `client_pubkey` are zeroed for unencrypted records. `payload` is
+/// the record body after the length prefix; depending on how the record was
+/// obtained it may still be encrypted, and it is always still compressed.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub offset: i64,
+    pub mode: RecordMode,
+    pub iv: [u8; 16],
+    pub client_pubkey: [u8; 64],
+    pub payload: Vec<u8>,
 }
 
 pub struct LogBufReaderV4<'a, T: Read> {
     reader: BufReader<T>,
     position: i64,
     cipher: &'a Cipher,
-    decompressor: Decompress, // use flate2::Decompress as mutable decompressor
+    registry: CodecRegistry,
+    decompressors: HashMap<u8, Box<dyn Decompressor>>, // one per compress mode, created on first use
+    version: FileVersion,
+    proto_name: Vec<u8>,
+    zlib_reset_interval: Option<NonZeroU32>,
+    curve: Option<Curve>,
+    metrics: Option<Arc<dyn DecodeMetrics>>,
+    /// Whether an encrypted record was inflated, i.e. the key is right.
+    decoded_encrypted: bool,
 }
 
 impl<'a, T: Read> LogBufReaderV4<'a, T> {
@@ -50,11 +102,74 @@ impl<'a, T: Read> LogBufReaderV4<'a, T> {
             reader: BufReader::new(reader),
             position: 0,
             cipher,
-            decompressor: Decompress::new_with_window_bits(false, 15),
+            registry: CodecRegistry::default(),
+            decompressors: HashMap::new(),
+            version: FileVersion::default(),
+            proto_name: Vec::new(),
+            zlib_reset_interval: None,
+            curve: None,
+            metrics: None,
+            decoded_encrypted: false,
+        }
+    }
+
+    /// Decodes records with the given compressors and ciphers instead of the
+    /// built-in ones.
+    pub fn with_registry(mut self, registry: CodecRegistry) -> Self {
+        self.registry = registry;
+        self.decompressors.clear();
+        self
+    }
+
+    /// Reports decoded records, failures and recovery skips to `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<dyn DecodeMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Passes the failure of `result`, if any, to the metrics hook.
+    fn observe<R>(&self, result: Result<R, LogBufReadError>) -> Result<R, LogBufReadError> {
+        if let (Err(e), Some(metrics)) = (&result, &self.metrics) {
+            metrics.decode_failed(DecodeFailure::of(e));
         }
+        result
+    }
+
+    /// File version from the header.
+    pub fn version(&self) -> FileVersion {
+        self.version
+    }
+
+    /// Offset of the next byte to be read.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn proto_name(&self) -> &[u8] {
+        &self.proto_name
+    }
+
+    /// Number of zlib records sharing one zlib stream, as recorded in a V5
+    /// header. `Some(1)` means every zlib record decodes on its own. The
+    /// reader resets its stream at each [`CompressMode::ZlibReset`] record,
+    /// whatever the interval.
+    pub fn zlib_reset_interval(&self) -> Option<NonZeroU32> {
+        self.zlib_reset_interval
+    }
+
+    /// Curve of the keys the records are encrypted with, if the V5 header
+    /// records it. Decrypting fails early when the reader's key is on
+    /// another curve.
+    pub fn curve(&self) -> Option<Curve> {
+        self.curve
     }
 
     pub fn read_header(&mut self) -> Result<(), LogBufReadError> {
+        let result = self.read_header_fields();
+        self.observe(result)
+    }
+
+    fn read_header_fields(&mut self) -> Result<(), LogBufReadError> {
         let magic: &mut [u8; 4] = &mut self.reader.read_u32::<LittleEndian>()?.to_le_bytes();
 
         if magic != &MAGIC_NUMBER {
@@ -63,19 +178,11 @@ impl<'a, T: Read> LogBufReaderV4<'a, T> {
 
         let version = self.reader.read_u8()?;
 
-        match FromPrimitive::from_u8(version) {
-            Some(FileVersion::V3) => {
-                // println!("version: 3");
-            }
-            Some(FileVersion::V4) => {
-                // println!("version: 4");
-            }
-            _ => {
-                return Err(LogBufReadError::InvalidVersion);
-            }
-        }
+        let version: FileVersion =
+            FromPrimitive::from_u8(version).ok_or(LogBufReadError::InvalidVersion)?;
 
-        self.read_remain_header()?;
+        self.version = version;
+        self.read_remain_header(version)?;
 
         Ok(())
     }
@@ -91,105 +198,294 @@ impl<'a, T: Read> LogBufReaderV4<'a, T> {
 
     fn read_log_length(&mut self) -> Result<i64, LogBufReadError> {
         let log_len = self.reader.read_u16::<LittleEndian>()?.into();
+        self.position += 2;
         if log_len <= 0 || log_len > SINGLE_LOG_CONTENT_MAX_LENGTH as i64 {
             return Err(LogBufReadError::InvalidLogLength);
         }
-        self.position += 2;
         Ok(log_len)
     }
 
-    fn read_remain_header(&mut self) -> Result<(), LogBufReadError> {
+    fn read_remain_header(&mut self, version: FileVersion) -> Result<(), LogBufReadError> {
         let proto_name_len: usize = self.reader.read_u16::<LittleEndian>()?.into();
         let mut name: Vec<u8> = vec![0; proto_name_len];
         self.reader.read_exact(&mut name)?;
-        // let proto_name = String::from_utf8(name)?;
-        // println!("proto_name: {}", proto_name);
-        self.read_sync_marker()?;
+        self.proto_name = name;
         self.position += 4 + 1 + 2 + proto_name_len as i64;
 
+        if let FileVersion::V5 = version {
+            self.read_header_flags()?;
+        }
+
+        self.read_sync_marker()?;
+
         Ok(())
     }
 
-    pub fn read_body(&mut self, out_buffer: &mut Vec<u8>) -> Result<i64, LogBufReadError> {
-        if self.reader.buffer().len() < 2 + 1 + 8 {
-            return Ok(-1);
+    fn read_header_flags(&mut self) -> Result<(), LogBufReadError> {
+        let flags = self.reader.read_u8()?;
+        self.position += 1;
+
+        if flags & !(HEADER_FLAG_ZLIB_RESET | HEADER_FLAG_CURVE) != 0 {
+            return Err(LogBufReadError::InvalidHeaderFlags(flags));
         }
 
-        let ms: i32 = self.reader.read_u8()?.into();
+        if flags & HEADER_FLAG_ZLIB_RESET != 0 {
+            let interval = self.reader.read_u32::<LittleEndian>()?;
+            self.zlib_reset_interval =
+                Some(NonZeroU32::new(interval).ok_or(LogBufReadError::InvalidHeaderFlags(flags))?);
+            self.position += 4;
+        }
 
-        let compress_mode = match ms >> 4 {
-            1 => CompressMode::None,
-            2 => CompressMode::Zlib,
-            _ => {
-                println!("illegal compress mode: {}", ms >> 4);
-                return Ok(-2);
-            }
-        };
+        if flags & HEADER_FLAG_CURVE != 0 {
+            let curve = self.reader.read_u8()?;
+            self.curve = Some(
+                Curve::try_from(curve).map_err(|_| LogBufReadError::InvalidHeaderFlags(flags))?,
+            );
+            self.position += 1;
+        }
 
-        let encrypt_mode = match ms & 0x0F {
-            1 => EncryptMode::None,
-            2 => EncryptMode::Aes,
-            _ => {
-                println!("illegal encrypt mode: {}", ms & 0x0F);
-                return Ok(-3);
-            }
+        Ok(())
+    }
+
+    /// Reads the next record as stored in the file, without decrypting or
+    /// decompressing its payload. Returns `None` at the end of the input.
+    pub fn read_raw_record(&mut self) -> Result<Option<LogRecord>, LogBufReadError> {
+        let result = match self.read_raw_record_fields() {
+            Ok(Some((_, false))) => Err(LogBufReadError::InvalidSyncMarker),
+            Ok(Some((record, true))) => Ok(Some(record)),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
         };
+        self.observe(result)
+    }
 
-        // println!("compress_mode: {:?}", compress_mode);
-        // println!("encrypt_mode: {:?}", encrypt_mode);
+    /// Like [`read_raw_record`](Self::read_raw_record), but reports whether
+    /// the sync marker after the record is intact instead of failing on it.
+    pub fn read_raw_record_lenient(
+        &mut self,
+    ) -> Result<Option<(LogRecord, bool)>, LogBufReadError> {
+        let result = self.read_raw_record_fields();
+        if let (Ok(Some((record, false))), Some(metrics)) = (&result, &self.metrics) {
+            metrics.recovery_skip(record.offset);
+        }
+        self.observe(result)
+    }
 
+    fn read_raw_record_fields(&mut self) -> Result<Option<(LogRecord, bool)>, LogBufReadError> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let offset = self.position;
+        let ms = self.reader.read_u8()?;
         self.position += 1;
 
-        #[allow(unused_assignments)]
-        let mut log_len: i64 = 0;
+        let mode = RecordMode::try_from(ms).map_err(|_| match ms >> 4 {
+            0 => LogBufReadError::InvalidCompressMode(0),
+            _ => LogBufReadError::InvalidEncryptMode(ms & 0x0F),
+        })?;
+
+        if !self.registry.supports_compression(mode.compress) {
+            return Err(LogBufReadError::InvalidCompressMode(ms >> 4));
+        }
 
-        let buf = match encrypt_mode {
-            EncryptMode::Aes => {
-                let iv = &self.reader.read_u128::<LittleEndian>()?.to_le_bytes();
-                let client_pubkey: &mut [u8; 64] = &mut [0; 64];
-                self.reader.read_exact(client_pubkey)?;
-                self.position += 16 + 64;
+        if !self.registry.supports_encryption(mode.encrypt) {
+            return Err(LogBufReadError::InvalidEncryptMode(ms & 0x0F));
+        }
 
-                log_len = self.read_log_length()?;
+        let mut iv = [0u8; 16];
+        let mut client_pubkey = [0u8; 64];
 
-                let mut buf = vec![0; log_len as usize];
-                self.reader.read_exact(&mut buf)?;
-                self.cipher
-                    .decrypt_inplace(client_pubkey, iv, &mut buf)
-                    .map_err(|_| LogBufReadError::DecryptionError)?;
+        if mode.encrypt != EncryptMode::None {
+            iv = self.reader.read_u128::<LittleEndian>()?.to_le_bytes();
+            self.reader.read_exact(&mut client_pubkey)?;
+            self.position += 16 + 64;
+        }
 
-                if buf.is_empty() {
-                    return Ok(-5);
-                }
+        let log_len = self.read_log_length()?;
+        let mut payload = vec![0; log_len as usize];
+        self.reader.read_exact(&mut payload)?;
+        self.position += log_len;
 
-                buf
-            }
-            EncryptMode::None => {
-                log_len = self.read_log_length()?;
-                let mut buf = vec![0; log_len as usize];
-                self.reader.read_exact(&mut buf)?;
-                buf
-            }
+        let sync_marker = self.reader.read_u64::<LittleEndian>()?.to_le_bytes();
+        self.position += 8;
+
+        let record = LogRecord {
+            offset,
+            mode,
+            iv,
+            client_pubkey,
+            payload,
         };
+        Ok(Some((record, sync_marker == SYNC_MARKER)))
+    }
+
+    /// Skips the input up to and including the next sync marker, so that
+    /// reading can resume with the record after a damaged one. Returns
+    /// `false` if the input ends first.
+    pub fn skip_to_sync_marker(&mut self) -> Result<bool, LogBufReadError> {
+        let start = self.position;
+        let marker = u64::from_be_bytes(SYNC_MARKER);
+        let mut window = 0u64;
+        let mut seen = 0usize;
 
-        match compress_mode {
-            CompressMode::None => {
-                out_buffer[..buf.len()].copy_from_slice(&buf);
-                log_len = buf.len() as i64;
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(false);
             }
-            CompressMode::Zlib => {
-                log_len =
-                    self.decompress_zlib(&buf, out_buffer)
-                        .map_err(|_| LogBufReadError::DecompressError)? as i64;
+
+            let mut consumed = 0;
+            let mut found = false;
+            for byte in buffer {
+                consumed += 1;
+                seen += 1;
+                window = window << 8 | *byte as u64;
+                if seen >= SYNC_MARKER.len() && window == marker {
+                    found = true;
+                    break;
+                }
+            }
+            self.reader.consume(consumed);
+            self.position += consumed as i64;
+
+            if found {
+                if let Some(metrics) = &self.metrics {
+                    metrics.recovery_skip(start);
+                }
+                return Ok(true);
             }
         }
+    }
 
-        self.read_sync_marker()?;
+    /// Decrypts the payload of a raw record in place, leaving it compressed.
+    pub fn decrypt_record(&self, record: &mut LogRecord) -> Result<(), LogBufReadError> {
+        if record.mode.encrypt == EncryptMode::None {
+            return Ok(());
+        }
 
-        Ok(log_len)
+        if let Some(curve) = self.curve.filter(|curve| *curve != self.cipher.curve()) {
+            return self.observe(Err(LogBufReadError::DecryptionError(
+                CipherError::CurveMismatch {
+                    expected: curve,
+                    actual: self.cipher.curve(),
+                },
+            )));
+        }
+
+        let record_cipher = self.observe(self.registry.cipher(record.mode.encrypt).ok_or(
+            LogBufReadError::InvalidEncryptMode(record.mode.encrypt.into()),
+        ))?;
+
+        let context = CipherContext {
+            peer_pub_key: &record.client_pubkey,
+            iv: &record.iv,
+            proto_name: &self.proto_name,
+        };
+
+        let result = record_cipher
+            .decrypt(self.cipher, &context, &mut record.payload)
+            .map_err(LogBufReadError::DecryptionError);
+        self.observe(result)
     }
 
-    pub fn read(&mut self, mut callback: impl FnMut(&str)) -> Result<(), LogBufReadError> {
+    /// Reads the next record and decrypts its payload. The payload is still
+    /// compressed according to `compress_mode`.
+    pub fn read_record(&mut self) -> Result<Option<LogRecord>, LogBufReadError> {
+        let mut record = match self.read_raw_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        self.decrypt_record(&mut record)?;
+        Ok(Some(record))
+    }
+
+    /// Decompresses a decrypted record payload into `out_buffer`. Records of
+    /// each compress mode share state, so they must be inflated in file order,
+    /// except that a [`CompressMode::ZlibReset`] record starts over (see
+    /// [`zlib_reset_interval`](Self::zlib_reset_interval)).
+    ///
+    /// Fails with [`LogBufReadError::WrongKey`] when the first encrypted
+    /// record does not inflate.
+    pub fn inflate_record(
+        &mut self,
+        record: &LogRecord,
+        out_buffer: &mut Vec<u8>,
+    ) -> Result<usize, LogBufReadError> {
+        let start = out_buffer.len();
+        let encrypted = record.mode.encrypt != EncryptMode::None;
+        let result = match self.decompress_record(record, out_buffer) {
+            Err(e) if encrypted && !self.decoded_encrypted => Err(LogBufReadError::WrongKey {
+                offset: record.offset,
+                source: Box::new(e),
+            }),
+            result => result,
+        };
+        self.decoded_encrypted |= encrypted && result.is_ok();
+        if let (Ok(_), Some(metrics)) = (&result, &self.metrics) {
+            metrics.record_decoded(record.payload.len(), out_buffer.len() - start);
+        }
+        self.observe(result)
+    }
+
+    fn decompress_record(
+        &mut self,
+        record: &LogRecord,
+        out_buffer: &mut Vec<u8>,
+    ) -> Result<usize, LogBufReadError> {
+        let codec = record.mode.compress.codec();
+        if record.mode.compress == CompressMode::ZlibReset {
+            self.decompressors.remove(&codec.into());
+        }
+
+        let decompressor = match self.decompressors.entry(codec.into()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                self.registry
+                    .new_decompressor(record.mode.compress)
+                    .map_err(|e| LogBufReadError::DecompressError {
+                        offset: record.offset,
+                        source: e.into(),
+                    })?,
+            ),
+        };
+
+        decompressor
+            .decompress(&record.payload, out_buffer)
+            .map_err(|e| LogBufReadError::DecompressError {
+                offset: record.offset,
+                source: e.into(),
+            })
+    }
+
+    /// Reads and inflates the next record into `out_buffer` and returns its
+    /// length, `-1` at the end of the input, or `-2` and `-3` for a record
+    /// with an unknown compress or encrypt mode. Failures of the latter kind
+    /// still reach the metrics hook.
+    pub fn read_body(&mut self, out_buffer: &mut Vec<u8>) -> Result<i64, LogBufReadError> {
+        let record = match self.read_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => return Ok(-1),
+            Err(LogBufReadError::InvalidCompressMode(_)) => return Ok(-2),
+            Err(LogBufReadError::InvalidEncryptMode(_)) => return Ok(-3),
+            Err(e) => return Err(e),
+        };
+
+        let log_len = self.inflate_record(&record, out_buffer)?;
+
+        Ok(log_len as i64)
+    }
+
+    pub fn read(&mut self, callback: impl FnMut(&str)) -> Result<(), LogBufReadError> {
+        let start = Instant::now();
+        let result = self.read_all(callback);
+        if let Some(metrics) = &self.metrics {
+            metrics.file_decoded(start.elapsed());
+        }
+        result
+    }
+
+    fn read_all(&mut self, mut callback: impl FnMut(&str)) -> Result<(), LogBufReadError> {
         let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
 
         self.read_header()?;
@@ -210,10 +506,429 @@ impl<'a, T: Read> LogBufReaderV4<'a, T> {
 
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogBufReadError, LogBufReaderV4, LogRecord};
+    use crate::{
+        cipher::{
+            aes_cfb_ecdh::Cipher,
+            error::CipherError,
+            key_pair::{Curve, KeyPair},
+        },
+        io::{
+            codec::{CodecRegistry, Decompressor, PayloadLengthError, ZlibDecompressor},
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode, FileVersion, SINGLE_LOG_CONTENT_MAX_LENGTH},
+        },
+    };
+    use anyhow::Result;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn test_read_write_round_trip() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let modes = [
+            (CompressMode::None, EncryptMode::None),
+            (CompressMode::None, EncryptMode::Aes),
+            (CompressMode::None, EncryptMode::AesHkdf),
+            (CompressMode::Zlib, EncryptMode::None),
+            (CompressMode::Zlib, EncryptMode::Aes),
+            (CompressMode::Zlib, EncryptMode::AesHkdf),
+            (CompressMode::Zstd, EncryptMode::None),
+            (CompressMode::Zstd, EncryptMode::Aes),
+            (CompressMode::Zstd, EncryptMode::AesHkdf),
+            (CompressMode::Lz4, EncryptMode::None),
+            (CompressMode::Lz4, EncryptMode::Aes),
+            (CompressMode::Lz4, EncryptMode::AesHkdf),
+        ];
+
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        for (i, mode) in modes.iter().enumerate() {
+            writer.write_single_log(mode, &server_key_pair.public_key, &format!("log {}", i))?;
+        }
+        drop(writer);
+
+        let mut logs = Vec::new();
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read(|content| logs.push(content.to_string()))?;
+
+        let expected: Vec<String> = (0..modes.len()).map(|i| format!("log {}", i)).collect();
+        assert_eq!(logs, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd_dictionary() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let logs: Vec<String> = (0..200)
+            .map(|i| {
+                format!(
+                    r#"{{"msg":"save:{}","level":"{}","userId":"uid{}"}}"#,
+                    i,
+                    i % 5,
+                    i % 7
+                )
+            })
+            .collect();
+        let dictionary = zstd::dict::from_samples(&logs, 4096)?;
+
+        let mut file = Vec::new();
+        let registry = CodecRegistry::default().with_zstd(3, &dictionary);
+
+        let mut writer =
+            LogBufWriterV4::new(&mut file, &client_cipher).with_registry(registry.clone());
+        writer.write_head()?;
+        for log in &logs {
+            writer.write_single_log(
+                (CompressMode::Zstd, EncryptMode::Aes),
+                &server_key_pair.public_key,
+                log,
+            )?;
+        }
+        drop(writer);
+
+        let mut read_logs = Vec::new();
+        LogBufReaderV4::new(file.as_slice(), &server_cipher)
+            .with_registry(registry)
+            .read(|content| read_logs.push(content.to_string()))?;
+        assert_eq!(read_logs, logs);
+
+        let without_dictionary = LogBufReaderV4::new(file.as_slice(), &server_cipher).read(|_| {});
+        assert!(without_dictionary.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zlib_reset_interval() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let logs: Vec<String> = (0..10)
+            .map(|i| format!(r#"{{"msg":"save:{}","userId":"uid{}"}}"#, i, i % 3))
+            .collect();
+
+        let write_file = |interval: NonZeroU32| -> Result<Vec<u8>> {
+            let mut file = Vec::new();
+            let mut writer =
+                LogBufWriterV4::new(&mut file, &client_cipher).with_zlib_reset_interval(interval);
+            writer.write_head()?;
+            for log in &logs {
+                writer.write_single_log(
+                    (CompressMode::Zlib, EncryptMode::Aes),
+                    &server_key_pair.public_key,
+                    log,
+                )?;
+            }
+            drop(writer);
+            Ok(file)
+        };
+
+        for interval in [NonZeroU32::MIN, NonZeroU32::new(3).unwrap()] {
+            let file = write_file(interval)?;
+
+            let mut read_logs = Vec::new();
+            let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+            reader.read(|content| read_logs.push(content.to_string()))?;
+            assert_eq!(reader.zlib_reset_interval(), Some(interval));
+            assert_eq!(read_logs, logs);
+
+            // a reader unaware of the resets inflates the records as one stream
+            let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+            reader.read_header()?;
+            let mut decompressor = ZlibDecompressor::default();
+            let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+            for log in &logs {
+                let record = reader.read_record()?.unwrap();
+                buffer.clear();
+                decompressor.decompress(&record.payload, &mut buffer)?;
+                assert_eq!(buffer, log.as_bytes());
+            }
+        }
+
+        // with an interval of 1, any record decodes on its own
+        let file = write_file(NonZeroU32::MIN)?;
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read_header()?;
+        let records: Vec<_> =
+            std::iter::from_fn(|| reader.read_record().transpose()).collect::<Result<_, _>>()?;
+        let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+        reader.inflate_record(&records[7], &mut buffer)?;
+        assert_eq!(buffer, logs[7].as_bytes());
+        assert!(records
+            .iter()
+            .all(|record| record.mode.compress == CompressMode::ZlibReset));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zlib_reset_recovery() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let logs: Vec<String> = (0..9)
+            .map(|i| format!(r#"{{"msg":"save:{}","userId":"uid{}"}}"#, i, i % 3))
+            .collect();
+
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher)
+            .with_zlib_reset_interval(NonZeroU32::new(3).unwrap());
+        writer.write_head()?;
+        for log in &logs {
+            writer.write_single_log(
+                (CompressMode::Zlib, EncryptMode::None),
+                &server_key_pair.public_key,
+                log,
+            )?;
+        }
+        drop(writer);
+
+        let read_records =
+            |file: &[u8]| -> Result<Vec<LogRecord>> {
+                let mut reader = LogBufReaderV4::new(file, &server_cipher);
+                reader.read_header()?;
+                Ok(std::iter::from_fn(|| reader.read_record().transpose())
+                    .collect::<Result<_, _>>()?)
+            };
+        let records = read_records(&file)?;
+        let modes: Vec<_> = records.iter().map(|record| record.mode.compress).collect();
+        assert_eq!(
+            modes,
+            [
+                CompressMode::ZlibReset,
+                CompressMode::Zlib,
+                CompressMode::Zlib
+            ]
+            .repeat(3)
+        );
+
+        // records skipped by the reader: decoding resumes at the next group
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read_header()?;
+        let mut buffer = Vec::new();
+        for (i, record) in records.iter().enumerate().skip(1) {
+            buffer.clear();
+            let result = reader.inflate_record(record, &mut buffer);
+            if i >= 3 {
+                result?;
+                assert_eq!(buffer, logs[i].as_bytes());
+            }
+        }
+
+        // a corrupt record in the middle of a group fails, and the groups
+        // after it still decode
+        let end = records[4].offset as usize + 1 + 2 + records[4].payload.len();
+        file[end - 1] ^= 0xFF;
+        let records = read_records(&file)?;
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read_header()?;
+        for (i, record) in records.iter().enumerate() {
+            buffer.clear();
+            let result = reader.inflate_record(record, &mut buffer);
+            match i {
+                4 => assert!(matches!(
+                    result,
+                    Err(LogBufReadError::DecompressError { offset, .. }) if offset == record.offset
+                )),
+                5 => {}
+                _ => {
+                    result?;
+                    assert_eq!(buffer, logs[i].as_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_error_offset() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        for log in ["first", "second"] {
+            writer.write_single_log(
+                (CompressMode::Zlib, EncryptMode::None),
+                &server_key_pair.public_key,
+                log,
+            )?;
+        }
+        drop(writer);
+
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read_header()?;
+        reader.read_record()?;
+        let second = reader.read_record()?.unwrap();
+
+        // corrupt the sync flush trailer of the second record
+        let end = second.offset as usize + 1 + 2 + second.payload.len();
+        file[end - 1] ^= 0xFF;
 
-    pub fn decompress_zlib(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<usize> {
-        self.decompressor
-            .decompress_vec(input, output, FlushDecompress::Sync)?;
-        Ok(output.len())
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        let result = reader.read(|_| {});
+        assert!(matches!(
+            result,
+            Err(LogBufReadError::DecompressError { offset, .. }) if offset == second.offset
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_length() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        // random bytes do not compress, so every mode grows them
+        let incompressible: Vec<u8> = (0..SINGLE_LOG_CONTENT_MAX_LENGTH)
+            .map(|_| rand::random())
+            .collect();
+
+        for compress in [
+            CompressMode::None,
+            CompressMode::Zlib,
+            CompressMode::Zstd,
+            CompressMode::Lz4,
+        ] {
+            let mode = (compress, EncryptMode::Aes);
+            let mut file = Vec::new();
+            let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+            writer.write_head()?;
+            writer.write_single_log(mode, &server_key_pair.public_key, "before")?;
+
+            let mut oversize = incompressible.clone();
+            if compress == CompressMode::None {
+                oversize.push(0);
+            }
+            let error = writer
+                .write_log_bytes(mode, &server_key_pair.public_key, &oversize)
+                .unwrap_err();
+            assert!(error.is::<PayloadLengthError>(), "{:?}", compress);
+
+            writer.write_single_log(mode, &server_key_pair.public_key, "after")?;
+            drop(writer);
+
+            let mut logs = Vec::new();
+            LogBufReaderV4::new(file.as_slice(), &server_cipher)
+                .read(|content| logs.push(content.to_string()))?;
+            assert_eq!(logs, ["before", "after"], "{:?}", compress);
+        }
+
+        // readers reject empty records as well
+        let mut writer = LogBufWriterV4::new(Vec::new(), &client_cipher);
+        let error = writer
+            .write_single_log(
+                (CompressMode::None, EncryptMode::None),
+                &server_key_pair.public_key,
+                "",
+            )
+            .unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(PayloadLengthError(0))));
+
+        Ok(())
+    }
+
+    /// A record that cannot be encrypted leaves the file as it was.
+    #[test]
+    fn test_write_failure() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        let mode = (CompressMode::Zlib, EncryptMode::Aes);
+        writer.write_single_log(mode, &server_key_pair.public_key, "before")?;
+        let length = writer.into_inner().get_ref().len();
+        let not_on_curve = format!("04{}", "00".repeat(64));
+        for pub_key in ["not hex", "04", not_on_curve.as_str()] {
+            assert!(writer.write_single_log(mode, pub_key, "lost").is_err());
+            assert!(writer
+                .write_compressed_log(mode, pub_key, b"lost".to_vec())
+                .is_err());
+            assert_eq!(writer.into_inner().get_ref().len(), length, "{}", pub_key);
+        }
+        let unregistered = (CompressMode::Zlib, EncryptMode::Other(9));
+        assert!(writer
+            .write_single_log(unregistered, &server_key_pair.public_key, "lost")
+            .is_err());
+        assert_eq!(writer.into_inner().get_ref().len(), length);
+        writer.write_single_log(mode, &server_key_pair.public_key, "after")?;
+        drop(writer);
+
+        let mut logs = Vec::new();
+        LogBufReaderV4::new(file.as_slice(), &server_cipher)
+            .read(|content| logs.push(content.to_string()))?;
+        assert_eq!(logs, ["before", "after"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_curve_header() -> Result<()> {
+        let server_key_pair = KeyPair::random_with_curve(Curve::P256)?;
+        let client_cipher = Cipher::new_with_curve(
+            &KeyPair::random_with_curve(Curve::P256)?.private_key,
+            Curve::P256,
+        )?;
+        let server_cipher = Cipher::new_with_curve(&server_key_pair.private_key, Curve::P256)?;
+
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        writer.write_single_log(
+            (CompressMode::Zlib, EncryptMode::Aes),
+            &server_key_pair.public_key,
+            "hello",
+        )?;
+        drop(writer);
+
+        let mut logs = Vec::new();
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &server_cipher);
+        reader.read(|content| logs.push(content.to_string()))?;
+        assert_eq!(reader.version(), FileVersion::V5);
+        assert_eq!(reader.curve(), Some(Curve::P256));
+        assert_eq!(logs, ["hello"]);
+
+        let k256_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &k256_cipher);
+        assert!(matches!(
+            reader.read(|_| {}),
+            Err(LogBufReadError::DecryptionError(
+                CipherError::CurveMismatch {
+                    expected: Curve::P256,
+                    actual: Curve::Secp256k1
+                }
+            ))
+        ));
+
+        // secp256k1 files keep the V4 header
+        let mut file = Vec::new();
+        LogBufWriterV4::new(&mut file, &k256_cipher).write_head()?;
+        let mut reader = LogBufReaderV4::new(file.as_slice(), &k256_cipher);
+        reader.read_header()?;
+        assert_eq!(reader.version(), FileVersion::V4);
+        assert_eq!(reader.curve(), None);
+
+        Ok(())
     }
 }
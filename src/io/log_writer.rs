@@ -1,19 +1,30 @@
-use crate::{cipher::aes_cfb_ecdh::Cipher, io::primitive::Mode};
+use super::{
+    codec::{check_payload_length, CipherContext, CodecRegistry, Compressor},
+    primitive::{
+        CompressMode, EncryptMode, FileVersion, RecordMode, DEFAULT_PROTO_NAME, HEADER_FLAG_CURVE,
+        HEADER_FLAG_ZLIB_RESET, MAGIC_NUMBER, SYNC_MARKER,
+    },
+};
+use crate::cipher::{aes_cfb_ecdh::Cipher, key_pair::Curve};
 use anyhow::Result;
 use byteorder::WriteBytesExt;
-use flate2::{Compress, Compression, FlushCompress};
 use num_traits::ToPrimitive;
-use std::io::{BufWriter, Write};
-
-use super::primitive::{
-    CompressMode, EncryptMode, FileVersion, MAGIC_NUMBER, SYNC_MARKER,
-    SINGLE_LOG_CONTENT_MAX_LENGTH,
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{BufWriter, Write},
+    num::NonZeroU32,
 };
 
 pub struct LogBufWriterV4<'a, W: Write> {
     writer: BufWriter<W>,
     cipher: &'a Cipher,
-    compressor: Compress,
+    registry: CodecRegistry,
+    compressors: HashMap<u8, Box<dyn Compressor>>, // one per compress mode, created on first use
+    proto_name: Vec<u8>,
+    zlib_reset_interval: Option<NonZeroU32>,
+    zlib_records: u64,
+    /// Last server key records were encrypted for.
+    checked_pub_key: Option<String>,
 }
 
 impl<'a, W: Write> LogBufWriterV4<'a, W> {
@@ -21,26 +32,77 @@ impl<'a, W: Write> LogBufWriterV4<'a, W> {
         Self {
             writer: BufWriter::new(writer),
             cipher,
-            compressor: Compress::new_with_window_bits(Compression::default(), false, 15),
+            registry: CodecRegistry::default(),
+            compressors: HashMap::new(),
+            proto_name: DEFAULT_PROTO_NAME.as_bytes().to_vec(),
+            zlib_reset_interval: None,
+            zlib_records: 0,
+            checked_pub_key: None,
         }
     }
 
+    /// Encodes records with the given compressors and ciphers instead of the
+    /// built-in ones.
+    pub fn with_registry(mut self, registry: CodecRegistry) -> Self {
+        self.registry = registry;
+        self.compressors.clear();
+        self
+    }
+
+    pub fn with_proto_name(mut self, proto_name: &[u8]) -> Self {
+        self.proto_name = proto_name.to_vec();
+        self
+    }
+
+    /// Starts a fresh zlib stream every `interval` zlib records, so that a
+    /// lost or corrupt record only affects the rest of its group; with an
+    /// interval of 1 every zlib record can be decoded on its own. The
+    /// interval is recorded in a V5 header and the first record of each
+    /// stream is written as [`CompressMode::ZlibReset`]. Readers that only
+    /// know V4 files reject such files.
+    pub fn with_zlib_reset_interval(mut self, interval: NonZeroU32) -> Self {
+        self.zlib_reset_interval = Some(interval);
+        self
+    }
+
     pub fn into_inner(&mut self) -> &mut BufWriter<W> {
         &mut self.writer
     }
 
+    /// Writes a V4 header, or a V5 one when the zlib stream is reset or the
+    /// cipher is not on the default curve.
     pub fn write_head(&mut self) -> Result<()> {
         let writer = &mut self.writer;
+        let curve = self.cipher.curve();
+        let mut flags = 0;
+        if self.zlib_reset_interval.is_some() {
+            flags |= HEADER_FLAG_ZLIB_RESET;
+        }
+        if curve != Curve::default() {
+            flags |= HEADER_FLAG_CURVE;
+        }
+        let version = match flags {
+            0 => FileVersion::default(),
+            _ => FileVersion::V5,
+        };
         writer.write_all(&MAGIC_NUMBER)?;
         writer.write_u8(
-            ToPrimitive::to_u8(&FileVersion::default())
-                .ok_or(anyhow::anyhow!("invalid file version"))?,
+            ToPrimitive::to_u8(&version).ok_or(anyhow::anyhow!("invalid file version"))?,
         )?;
 
-        let proto_name = b"ATRealTimeLog";
-        let proto_name_length = proto_name.len() as u16;
+        let proto_name_length = self.proto_name.len() as u16;
         writer.write_u16::<byteorder::LittleEndian>(proto_name_length)?;
-        writer.write_all(proto_name)?;
+        writer.write_all(&self.proto_name)?;
+
+        if flags != 0 {
+            writer.write_u8(flags)?;
+        }
+        if let Some(interval) = self.zlib_reset_interval {
+            writer.write_u32::<byteorder::LittleEndian>(interval.get())?;
+        }
+        if flags & HEADER_FLAG_CURVE != 0 {
+            writer.write_u8(curve.into())?;
+        }
         writer.write_all(&SYNC_MARKER)?;
         writer.flush()?;
         Ok(())
@@ -48,56 +110,117 @@ impl<'a, W: Write> LogBufWriterV4<'a, W> {
 
     pub fn write_single_log(
         &mut self,
-        mode_tuple: &(CompressMode, EncryptMode),
+        mode: impl Into<RecordMode>,
         pub_key: &str,
         body: &str,
     ) -> Result<()> {
-        let mode: Mode = mode_tuple.into();
-        let mode_primitive = ToPrimitive::to_u8(&mode).ok_or(anyhow::anyhow!("invalid mode"))?;
-        // println!(
-        //     "compress: {:?}, encrypt: {:?}, log: {}",
-        //     mode_tuple.0, mode_tuple.1, body
-        // );
-        self.writer.write_u8(mode_primitive)?;
-
-        let mut log_body = body.as_bytes().to_vec();
-
-        match mode_tuple.0 {
-            CompressMode::None => {}
-            CompressMode::Zlib => {
-                log_body = self.compress_zlib(&log_body)?;
+        self.write_log_bytes(mode, pub_key, body.as_bytes())
+    }
+
+    pub fn write_log_bytes(
+        &mut self,
+        mode: impl Into<RecordMode>,
+        pub_key: &str,
+        body: &[u8],
+    ) -> Result<()> {
+        let mut mode = mode.into();
+        // `Other(2)` is zlib, and resets are decided here
+        mode.compress = CompressMode::from(u8::from(mode.compress)).codec();
+        self.check_encryption(mode.encrypt, pub_key)?;
+
+        let zlib = mode.compress == CompressMode::Zlib;
+        if let (true, Some(interval)) = (zlib, self.zlib_reset_interval) {
+            if self.zlib_records.is_multiple_of(interval.get().into()) {
+                self.compressors.remove(&mode.compress.into());
+                mode.compress = CompressMode::ZlibReset;
             }
         }
 
-        match mode_tuple.1 {
-            EncryptMode::None => {}
-            EncryptMode::Aes => {
-                let client_secret = self.cipher.get_key_pair();
-                let server_pub_key = hex::decode(pub_key)?;
-                let client_pub_key = client_secret.to_public_key_untagged_bytes()?;
-                let iv = Cipher::random_iv();
-                self.writer.write_all(&iv)?;
-                self.writer.write_all(&client_pub_key)?;
-
-                self.cipher
-                    .encrypt_inplace(&server_pub_key, &iv, &mut log_body)?;
-            }
+        let compressor = match self.compressors.entry(mode.compress.codec().into()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.registry.new_compressor(mode.compress)?),
+        };
+        let log_body = compressor.compress(body)?;
+
+        self.write_compressed_log(mode, pub_key, log_body)?;
+        if zlib {
+            self.zlib_records += 1;
         }
+        Ok(())
+    }
 
-        let log_length = log_body.len() as u16;
+    /// Fails if records cannot be encrypted for `pub_key`. Checked before a
+    /// compressor takes in the record, since streaming compressors cannot
+    /// take it back.
+    fn check_encryption(&mut self, encrypt: EncryptMode, pub_key: &str) -> Result<()> {
+        if encrypt == EncryptMode::None {
+            return Ok(());
+        }
+        if !self.registry.supports_encryption(encrypt) {
+            anyhow::bail!("unsupported encrypt mode: {:?}", encrypt);
+        }
+        if self.checked_pub_key.as_deref() != Some(pub_key) {
+            self.cipher.get_shared_key(&hex::decode(pub_key)?)?;
+            self.checked_pub_key = Some(pub_key.to_string());
+        }
+        Ok(())
+    }
 
-        self.writer
-            .write_u16::<byteorder::LittleEndian>(log_length)?;
-        self.writer.write_all(&log_body)?;
-        self.writer.write_all(&SYNC_MARKER)?;
+    /// Writes a record whose payload is already compressed according to
+    /// `mode.compress`, encrypting it for `pub_key` when requested. Payloads
+    /// must come from one compressor per mode, in order.
+    ///
+    /// The record is written in one piece once it is encoded: if the payload
+    /// length is one readers would reject
+    /// ([`PayloadLengthError`](super::codec::PayloadLengthError)), the encrypt
+    /// mode is not registered or `pub_key` is not a valid key, it fails
+    /// writing nothing.
+    pub fn write_compressed_log(
+        &mut self,
+        mode: impl Into<RecordMode>,
+        pub_key: &str,
+        log_body: Vec<u8>,
+    ) -> Result<()> {
+        let record = self.encode_record(mode.into(), pub_key, log_body)?;
+        self.writer.write_all(&record)?;
         self.writer.flush()?;
         Ok(())
     }
 
-    pub fn compress_zlib(&mut self, bytes: &[u8]) -> Result<Vec<u8>> {
-        let mut output: Vec<u8> = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
-        self.compressor
-            .compress_vec(bytes, &mut output, FlushCompress::Sync)?;
-        Ok(output)
+    fn encode_record(
+        &self,
+        mode: RecordMode,
+        pub_key: &str,
+        mut log_body: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        check_payload_length(&log_body)?;
+
+        let mut record = Vec::with_capacity(log_body.len() + 128);
+        record.write_u8(mode.into())?;
+
+        if mode.encrypt != EncryptMode::None {
+            let record_cipher = self.registry.cipher(mode.encrypt).ok_or(anyhow::anyhow!(
+                "unsupported encrypt mode: {:?}",
+                mode.encrypt
+            ))?;
+            let client_secret = self.cipher.get_key_pair();
+            let server_pub_key = hex::decode(pub_key)?;
+            let client_pub_key = client_secret.to_public_key_untagged_bytes()?;
+            let iv = Cipher::random_iv();
+
+            let context = CipherContext {
+                peer_pub_key: &server_pub_key,
+                iv: &iv,
+                proto_name: &self.proto_name,
+            };
+            record_cipher.encrypt(self.cipher, &context, &mut log_body)?;
+            record.write_all(&iv)?;
+            record.write_all(&client_pub_key)?;
+        }
+
+        record.write_u16::<byteorder::LittleEndian>(log_body.len() as u16)?;
+        record.write_all(&log_body)?;
+        record.write_all(&SYNC_MARKER)?;
+        Ok(record)
     }
 }
@@ -0,0 +1,337 @@
+use super::{
+    codec::CodecRegistry,
+    log_reader::{LogBufReadError, LogBufReaderV4},
+    primitive::SINGLE_LOG_CONTENT_MAX_LENGTH,
+};
+use crate::{cipher::aes_cfb_ecdh::Cipher, entry::GlogEntry};
+use chrono::{DateTime, FixedOffset};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc,
+    },
+    thread,
+};
+use thiserror::Error;
+
+/// Records decoded ahead of the merge, per source.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Error)]
+#[error("failed to read {name}")]
+pub struct MergeError {
+    /// Name of the source that failed.
+    pub name: Arc<str>,
+    #[source]
+    pub error: LogBufReadError,
+}
+
+/// A decoded record and where it came from.
+#[derive(Debug, Clone)]
+pub struct MergedRecord {
+    pub source: Arc<str>,
+    /// Position of the source in the order it was added.
+    pub source_index: usize,
+    /// Position of the record in its source, starting at 0.
+    pub index: usize,
+    pub offset: i64,
+    /// Timestamp of the record, if its content has one.
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub content: String,
+}
+
+/// Decodes several glog files or readers concurrently and yields their
+/// records as one stream, ordered by payload timestamp.
+///
+/// Each source is assumed to be in time order already. Records without a
+/// timestamp sort as if they had the timestamp of the record before them in
+/// their source, so they keep their place; ties go to the source added first.
+pub struct MergeReader {
+    cipher: Cipher,
+    registry: CodecRegistry,
+    sources: Vec<(Arc<str>, Box<dyn Read + Send>)>,
+}
+
+impl MergeReader {
+    pub fn new(cipher: &Cipher) -> Self {
+        Self {
+            cipher: cipher.clone(),
+            registry: CodecRegistry::default(),
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn with_registry(mut self, registry: CodecRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    pub fn add_reader(&mut self, name: impl Into<String>, reader: impl Read + Send + 'static) {
+        self.sources.push((name.into().into(), Box::new(reader)));
+    }
+
+    /// Adds a file, named by its path.
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        self.add_reader(path.to_string_lossy(), file);
+        Ok(())
+    }
+
+    /// Starts one decoding thread per source and returns the merged records.
+    /// A source that fails yields its error once and then ends; the others
+    /// carry on.
+    pub fn records(self) -> MergedRecords {
+        let receivers = self
+            .sources
+            .into_iter()
+            .enumerate()
+            .map(|(source_index, (name, reader))| {
+                let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+                let cipher = self.cipher.clone();
+                let registry = self.registry.clone();
+                thread::spawn(move || {
+                    decode_source(source_index, name, reader, cipher, registry, sender)
+                });
+                receiver
+            })
+            .collect();
+
+        MergedRecords {
+            receivers,
+            heap: BinaryHeap::new(),
+            primed: 0,
+        }
+    }
+}
+
+/// A record with the timestamp used for ordering.
+struct Pending {
+    sort_timestamp: Option<DateTime<FixedOffset>>,
+    record: MergedRecord,
+}
+
+impl Pending {
+    fn key(&self) -> (Option<&DateTime<FixedOffset>>, usize, usize) {
+        (
+            self.sort_timestamp.as_ref(),
+            self.record.source_index,
+            self.record.index,
+        )
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+type SourceItem = Result<Pending, MergeError>;
+
+fn decode_source(
+    source_index: usize,
+    name: Arc<str>,
+    reader: Box<dyn Read + Send>,
+    cipher: Cipher,
+    registry: CodecRegistry,
+    sender: SyncSender<SourceItem>,
+) {
+    let fail = |error| {
+        let _ = sender.send(Err(MergeError {
+            name: name.clone(),
+            error,
+        }));
+    };
+
+    let mut reader = LogBufReaderV4::new(reader, &cipher).with_registry(registry);
+    if let Err(error) = reader.read_header() {
+        return fail(error);
+    }
+
+    let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+    let mut sort_timestamp = None;
+    let mut index = 0;
+
+    loop {
+        let record = match reader.read_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => return,
+            Err(error) => return fail(error),
+        };
+
+        buffer.clear();
+        if let Err(error) = reader.inflate_record(&record, &mut buffer) {
+            return fail(error);
+        }
+
+        let content = String::from_utf8_lossy(&buffer).into_owned();
+        let timestamp = GlogEntry::parse(&content).and_then(|entry| entry.timestamp);
+        if timestamp.is_some() {
+            sort_timestamp = timestamp;
+        }
+
+        let pending = Pending {
+            sort_timestamp,
+            record: MergedRecord {
+                source: name.clone(),
+                source_index,
+                index,
+                offset: record.offset,
+                timestamp,
+                content,
+            },
+        };
+        if sender.send(Ok(pending)).is_err() {
+            return; // the merge was dropped
+        }
+        index += 1;
+    }
+}
+
+pub struct MergedRecords {
+    receivers: Vec<Receiver<SourceItem>>,
+    heap: BinaryHeap<Reverse<Pending>>,
+    primed: usize,
+}
+
+impl MergedRecords {
+    /// Waits for the next record of a source and queues it, or returns the
+    /// source's error. Sources end after reporting an error.
+    fn pull(&mut self, source_index: usize) -> Result<(), MergeError> {
+        match self.receivers[source_index].recv() {
+            Ok(Ok(pending)) => self.heap.push(Reverse(pending)),
+            Ok(Err(error)) => return Err(error),
+            Err(_) => {} // source finished
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for MergedRecords {
+    type Item = Result<MergedRecord, MergeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // wait for the first record of every source before yielding any
+        while self.primed < self.receivers.len() {
+            let source_index = self.primed;
+            self.primed += 1;
+            if let Err(error) = self.pull(source_index) {
+                return Some(Err(error));
+            }
+        }
+
+        let Reverse(pending) = self.heap.pop()?;
+        if let Err(error) = self.pull(pending.record.source_index) {
+            // the failed source has ended, yield the record next time
+            self.heap.push(Reverse(pending));
+            return Some(Err(error));
+        }
+        Some(Ok(pending.record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MergeReader;
+    use crate::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use anyhow::Result;
+
+    fn write_file(server_key_pair: &KeyPair, logs: &[String]) -> Result<Vec<u8>> {
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        for log in logs {
+            writer.write_single_log(
+                (CompressMode::Zlib, EncryptMode::Aes),
+                &server_key_pair.public_key,
+                log,
+            )?;
+        }
+        drop(writer);
+        Ok(file)
+    }
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let server_key_pair = KeyPair::random()?;
+        let server_cipher = Cipher::new(&server_key_pair.private_key)?;
+
+        let log = |msg: &str, second: u32| {
+            format!(
+                r#"{{"msg":"{}","timestamp":"2023-08-03 08:00:{:02} +0000"}}"#,
+                msg, second
+            )
+        };
+        let files = [
+            vec![
+                log("a1", 1),
+                log("a4", 4),
+                "a-plain".to_string(),
+                log("a6", 6),
+            ],
+            vec![log("b2", 2), log("b3", 3), log("b5", 5)],
+            vec![],
+        ];
+
+        let mut merge = MergeReader::new(&server_cipher);
+        for (i, logs) in files.iter().enumerate() {
+            merge.add_reader(
+                format!("file{}", i),
+                std::io::Cursor::new(write_file(&server_key_pair, logs)?),
+            );
+        }
+        merge.add_reader("broken", std::io::Cursor::new(b"not a glog file".to_vec()));
+
+        let mut records = Vec::new();
+        let mut errors = Vec::new();
+        for item in merge.records() {
+            match item {
+                Ok(record) => records.push(record),
+                Err(error) => errors.push(error.name.to_string()),
+            }
+        }
+
+        let messages: Vec<_> = records
+            .iter()
+            .map(|record| {
+                crate::entry::GlogEntry::parse(&record.content)
+                    .and_then(|entry| entry.msg)
+                    .unwrap_or(record.content.clone())
+            })
+            .collect();
+        assert_eq!(messages, ["a1", "b2", "b3", "a4", "a-plain", "b5", "a6"]);
+        assert_eq!(&*records[4].source, "file0");
+        assert_eq!(records[4].index, 2);
+        assert!(records[4].timestamp.is_none());
+        assert_eq!(errors, ["broken"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::log_reader::LogBufReadError;
+use std::time::Duration;
+
+/// Why a record failed to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecodeFailure {
+    Decrypt,
+    Decompress,
+    InvalidSyncMarker,
+    /// Truncated input, unknown modes and other damage.
+    Other,
+}
+
+impl DecodeFailure {
+    pub fn of(error: &LogBufReadError) -> Self {
+        match error {
+            LogBufReadError::DecryptionError(_)
+            | LogBufReadError::InvalidSecret
+            | LogBufReadError::WrongKey { .. } => DecodeFailure::Decrypt,
+            LogBufReadError::DecompressError { .. } => DecodeFailure::Decompress,
+            LogBufReadError::InvalidSyncMarker => DecodeFailure::InvalidSyncMarker,
+            _ => DecodeFailure::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecodeFailure::Decrypt => "decrypt",
+            DecodeFailure::Decompress => "decompress",
+            DecodeFailure::InvalidSyncMarker => "invalid_sync_marker",
+            DecodeFailure::Other => "other",
+        }
+    }
+}
+
+/// Receives decoding events from a reader, e.g. to export them as metrics.
+/// Every method does nothing by default.
+pub trait DecodeMetrics: Send + Sync {
+    /// A record was decoded from `bytes_in` bytes of payload into `bytes_out`
+    /// bytes of content.
+    fn record_decoded(&self, _bytes_in: usize, _bytes_out: usize) {}
+
+    fn decode_failed(&self, _failure: DecodeFailure) {}
+
+    /// A record with a damaged sync marker was read anyway, see
+    /// [`read_raw_record_lenient`](super::log_reader::LogBufReaderV4::read_raw_record_lenient),
+    /// or the input from `offset` on was skipped up to the next sync marker,
+    /// see [`skip_to_sync_marker`](super::log_reader::LogBufReaderV4::skip_to_sync_marker).
+    fn recovery_skip(&self, _offset: i64) {}
+
+    /// A whole file was decoded, successfully or not.
+    fn file_decoded(&self, _elapsed: Duration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeFailure, DecodeMetrics};
+    use crate::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            log_reader::LogBufReaderV4,
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use anyhow::Result;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl DecodeMetrics for Events {
+        fn record_decoded(&self, bytes_in: usize, bytes_out: usize) {
+            let event = format!("decoded {} {}", bytes_in > 0, bytes_out);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn decode_failed(&self, failure: DecodeFailure) {
+            let event = format!("failed {}", failure.as_str());
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn recovery_skip(&self, offset: i64) {
+            self.0.lock().unwrap().push(format!("skip {}", offset));
+        }
+
+        fn file_decoded(&self, _elapsed: Duration) {
+            self.0.lock().unwrap().push("file".to_string());
+        }
+    }
+
+    #[test]
+    fn test_decode_metrics() -> Result<()> {
+        let cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &cipher);
+        writer.write_head()?;
+        let first_offset = writer.into_inner().get_ref().len();
+        let mut ends = Vec::new();
+        for log in ["first", "second"] {
+            writer.write_single_log((CompressMode::None, EncryptMode::None), "", log)?;
+            ends.push(writer.into_inner().get_ref().len());
+        }
+        drop(writer);
+
+        // damage the sync marker after the first record
+        file[ends[0] - 1] ^= 0xFF;
+
+        let events = Arc::new(Events::default());
+        let mut reader = LogBufReaderV4::new(&file[..], &cipher).with_metrics(events.clone());
+        assert!(reader.read(|_| {}).is_err());
+
+        let mut reader = LogBufReaderV4::new(&file[..], &cipher).with_metrics(events.clone());
+        reader.read_header()?;
+        let mut buffer = Vec::new();
+        while let Some((record, _)) = reader.read_raw_record_lenient()? {
+            reader.inflate_record(&record, &mut buffer)?;
+        }
+
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                "failed invalid_sync_marker".to_string(),
+                "file".to_string(),
+                format!("skip {}", first_offset),
+                "decoded true 5".to_string(),
+                "decoded true 6".to_string(),
+            ]
+        );
+        Ok(())
+    }
+}
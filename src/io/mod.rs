@@ -1,3 +1,10 @@
+pub mod codec;
+pub mod follow;
+pub mod input;
+pub mod keyring;
 pub mod log_reader;
 pub mod log_writer;
+pub mod merge;
+pub mod metrics;
 pub mod primitive;
+pub mod reencrypt;
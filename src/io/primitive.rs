@@ -1,46 +1,165 @@
 use num_derive::{FromPrimitive, ToPrimitive};
+use thiserror::Error;
 
-#[derive(FromPrimitive, ToPrimitive, Default)]
+#[derive(FromPrimitive, ToPrimitive, Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum FileVersion {
     V3 = 3,
 
     #[default]
     V4 = 4,
+
+    /// V4 plus a header flags byte; written only when a flag is set.
+    V5 = 5,
 }
 
-#[derive(Debug, FromPrimitive, Default)]
+/// High nibble of a record's mode byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum CompressMode {
     #[default]
-    None = 1,
-    Zlib = 2,
+    None,
+    Zlib,
+    Zstd,
+    Lz4,
+    /// A zlib record that starts a fresh zlib stream, written at the reset
+    /// points of a V5 file (see [`HEADER_FLAG_ZLIB_RESET`]).
+    ZlibReset,
+    /// A mode registered in a `CodecRegistry`, by nibble value (1 to 15).
+    Other(u8),
+}
+
+impl CompressMode {
+    /// The mode whose compressor and decompressor handle records of this
+    /// mode: zlib for [`CompressMode::ZlibReset`], the mode itself otherwise.
+    pub fn codec(self) -> CompressMode {
+        match self {
+            CompressMode::ZlibReset => CompressMode::Zlib,
+            other => other,
+        }
+    }
 }
 
-#[derive(Debug, FromPrimitive, Default)]
+impl From<u8> for CompressMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => CompressMode::None,
+            2 => CompressMode::Zlib,
+            3 => CompressMode::Zstd,
+            4 => CompressMode::Lz4,
+            5 => CompressMode::ZlibReset,
+            other => CompressMode::Other(other),
+        }
+    }
+}
+
+impl From<CompressMode> for u8 {
+    fn from(mode: CompressMode) -> u8 {
+        match mode {
+            CompressMode::None => 1,
+            CompressMode::Zlib => 2,
+            CompressMode::Zstd => 3,
+            CompressMode::Lz4 => 4,
+            CompressMode::ZlibReset => 5,
+            CompressMode::Other(other) => other,
+        }
+    }
+}
+
+/// Low nibble of a record's mode byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum EncryptMode {
     #[default]
-    None = 1,
-    Aes = 2,
+    None,
+    Aes,
+    AesHkdf,
+    /// A mode registered in a `CodecRegistry`, by nibble value (1 to 15).
+    Other(u8),
 }
 
-#[derive(Debug, FromPrimitive, ToPrimitive)]
-pub enum Mode {
-    M11 = 0x11,
-    M12 = 0x12,
-    M21 = 0x21,
-    M22 = 0x22,
+impl From<u8> for EncryptMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => EncryptMode::None,
+            2 => EncryptMode::Aes,
+            3 => EncryptMode::AesHkdf,
+            other => EncryptMode::Other(other),
+        }
+    }
 }
 
-impl From<&(CompressMode, EncryptMode)> for Mode {
-    fn from(mode: &(CompressMode, EncryptMode)) -> Mode {
+impl From<EncryptMode> for u8 {
+    fn from(mode: EncryptMode) -> u8 {
         match mode {
-            (CompressMode::None, EncryptMode::None) => Mode::M11,
-            (CompressMode::None, EncryptMode::Aes) => Mode::M12,
-            (CompressMode::Zlib, EncryptMode::None) => Mode::M21,
-            (CompressMode::Zlib, EncryptMode::Aes) => Mode::M22,
+            EncryptMode::None => 1,
+            EncryptMode::Aes => 2,
+            EncryptMode::AesHkdf => 3,
+            EncryptMode::Other(other) => other,
         }
     }
 }
 
+#[derive(Debug, Error)]
+#[error("invalid record mode: {0:#04x}")]
+pub struct InvalidModeError(pub u8);
+
+/// The mode byte in front of every record: compression in the high nibble,
+/// encryption in the low one. Zero nibbles are never valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecordMode {
+    pub compress: CompressMode,
+    pub encrypt: EncryptMode,
+}
+
+impl RecordMode {
+    pub fn new(compress: CompressMode, encrypt: EncryptMode) -> Self {
+        Self { compress, encrypt }
+    }
+}
+
+impl TryFrom<u8> for RecordMode {
+    type Error = InvalidModeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value >> 4 == 0 || value & 0x0F == 0 {
+            return Err(InvalidModeError(value));
+        }
+
+        Ok(Self {
+            compress: (value >> 4).into(),
+            encrypt: (value & 0x0F).into(),
+        })
+    }
+}
+
+impl From<RecordMode> for u8 {
+    fn from(mode: RecordMode) -> u8 {
+        let compress: u8 = mode.compress.into();
+        let encrypt: u8 = mode.encrypt.into();
+        (compress & 0x0F) << 4 | (encrypt & 0x0F)
+    }
+}
+
+impl From<(CompressMode, EncryptMode)> for RecordMode {
+    fn from((compress, encrypt): (CompressMode, EncryptMode)) -> Self {
+        Self { compress, encrypt }
+    }
+}
+
+impl From<&(CompressMode, EncryptMode)> for RecordMode {
+    fn from(mode: &(CompressMode, EncryptMode)) -> Self {
+        (*mode).into()
+    }
+}
+
+pub const DEFAULT_PROTO_NAME: &str = "ATRealTimeLog";
 pub const SINGLE_LOG_CONTENT_MAX_LENGTH: usize = 16 * 1024;
 pub const MAGIC_NUMBER: [u8; 4] = [0x1B, 0xAD, 0xC0, 0xDE];
+/// V5 header flag: the writer starts a fresh zlib stream every N zlib
+/// records, with N stored as a little-endian `u32` after the flags byte.
+/// The first record of each stream has compress mode
+/// [`CompressMode::ZlibReset`].
+pub const HEADER_FLAG_ZLIB_RESET: u8 = 0x01;
+/// V5 header flag: encrypted records use keys on the curve stored as one
+/// byte after the flags and the zlib reset interval, `1` for secp256k1 and
+/// `2` for P-256. Without it the curve is not recorded.
+pub const HEADER_FLAG_CURVE: u8 = 0x02;
 pub const SYNC_MARKER: [u8; 8] = [0xB7, 0xDB, 0xE7, 0xDB, 0x80, 0xAD, 0xD9, 0x57];
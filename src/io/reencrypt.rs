@@ -0,0 +1,148 @@
+use super::{
+    codec::CodecRegistry, log_reader::LogBufReaderV4, log_writer::LogBufWriterV4,
+    primitive::SINGLE_LOG_CONTENT_MAX_LENGTH,
+};
+use crate::cipher::{
+    aes_cfb_ecdh::Cipher,
+    key_pair::{Curve, KeyPair},
+};
+use anyhow::Result;
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, Default)]
+pub struct ReencryptOptions {
+    /// Curve of the new recipient public key.
+    pub curve: Curve,
+
+    /// Forward compressed payloads untouched instead of inflating and
+    /// compressing them again. The output then carries the input's zlib
+    /// stream verbatim.
+    pub passthrough_compressed: bool,
+
+    /// Dictionary of zstd records, used for both reading and writing.
+    pub zstd_dictionary: Option<Vec<u8>>,
+}
+
+/// Rewrites a V4 file so that its encrypted records can be read with the
+/// private key matching `new_pub_key` instead of `cipher`.
+///
+/// Records keep their order, compression mode and encryption mode; plain
+/// records are copied as they are. Returns the number of records written.
+pub fn reencrypt<R: Read, W: Write>(
+    input: R,
+    output: W,
+    cipher: &Cipher,
+    new_pub_key: &str,
+    options: &ReencryptOptions,
+) -> Result<usize> {
+    let client_cipher = Cipher::new_with_curve(
+        &KeyPair::random_with_curve(options.curve)?.private_key,
+        options.curve,
+    )?;
+
+    let mut reader = LogBufReaderV4::new(input, cipher);
+    let mut writer = LogBufWriterV4::new(output, &client_cipher);
+
+    if let Some(dictionary) = &options.zstd_dictionary {
+        let registry =
+            CodecRegistry::default().with_zstd(zstd::DEFAULT_COMPRESSION_LEVEL, dictionary);
+        reader = reader.with_registry(registry.clone());
+        writer = writer.with_registry(registry);
+    }
+
+    reader.read_header()?;
+    let mut writer = writer.with_proto_name(reader.proto_name());
+    if let Some(interval) = reader.zlib_reset_interval() {
+        writer = writer.with_zlib_reset_interval(interval);
+    }
+    writer.write_head()?;
+
+    let mut buffer = Vec::with_capacity(SINGLE_LOG_CONTENT_MAX_LENGTH);
+    let mut count = 0;
+
+    while let Some(record) = reader.read_record()? {
+        if options.passthrough_compressed {
+            writer.write_compressed_log(record.mode, new_pub_key, record.payload)?;
+        } else {
+            buffer.clear();
+            reader.inflate_record(&record, &mut buffer)?;
+            writer.write_log_bytes(record.mode, new_pub_key, &buffer)?;
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reencrypt, ReencryptOptions};
+    use crate::{
+        cipher::{aes_cfb_ecdh::Cipher, key_pair::KeyPair},
+        io::{
+            log_reader::LogBufReaderV4,
+            log_writer::LogBufWriterV4,
+            primitive::{CompressMode, EncryptMode},
+        },
+    };
+    use anyhow::Result;
+
+    fn read_all(file: &[u8], cipher: &Cipher) -> Result<Vec<String>> {
+        let mut logs = Vec::new();
+        LogBufReaderV4::new(file, cipher).read(|content| logs.push(content.to_string()))?;
+        Ok(logs)
+    }
+
+    #[test]
+    fn test_reencrypt() -> Result<()> {
+        let old_key_pair = KeyPair::random()?;
+        let new_key_pair = KeyPair::random()?;
+        let old_cipher = Cipher::new(&old_key_pair.private_key)?;
+        let new_cipher = Cipher::new(&new_key_pair.private_key)?;
+        let client_cipher = Cipher::new(&KeyPair::random()?.private_key)?;
+
+        let modes = [
+            (CompressMode::Zlib, EncryptMode::Aes),
+            (CompressMode::None, EncryptMode::None),
+            (CompressMode::Zlib, EncryptMode::AesHkdf),
+            (CompressMode::None, EncryptMode::Aes),
+            (CompressMode::Zstd, EncryptMode::Aes),
+            (CompressMode::Lz4, EncryptMode::AesHkdf),
+        ];
+
+        let mut file = Vec::new();
+        let mut writer = LogBufWriterV4::new(&mut file, &client_cipher);
+        writer.write_head()?;
+        for i in 0..200 {
+            let mode = &modes[i % modes.len()];
+            writer.write_single_log(mode, &old_key_pair.public_key, &format!("log {}", i))?;
+        }
+        drop(writer);
+
+        let expected = read_all(&file, &old_cipher)?;
+        assert_eq!(expected.len(), 200);
+
+        for passthrough_compressed in [false, true] {
+            let options = ReencryptOptions {
+                passthrough_compressed,
+                ..Default::default()
+            };
+
+            let mut output = Vec::new();
+            let count = reencrypt(
+                file.as_slice(),
+                &mut output,
+                &old_cipher,
+                &new_key_pair.public_key,
+                &options,
+            )?;
+
+            assert_eq!(count, 200);
+            assert_eq!(read_all(&output, &new_cipher)?, expected);
+            assert!(read_all(&output, &old_cipher).is_err());
+        }
+
+        Ok(())
+    }
+}
@@ -1,2 +1,5 @@
 pub mod cipher;
+pub mod entry;
+pub mod export;
+pub mod format;
 pub mod io;